@@ -0,0 +1,164 @@
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, Values};
+use crate::error::{err, Result};
+use crate::output::Output;
+use crate::primitive::{Primitive, PrimitiveCodec};
+
+/// The largest alignment of an [`AlignedCodec`].
+pub const MAX_ALIGN: usize = 8;
+
+/// A [`PrimitiveCodec`] column padded to the alignment of `T` (relative to the start of the
+/// output) for [`EncodeOptions::align`](crate::EncodeOptions::align). The padding is preceded by
+/// its length, since the decoder doesn't know where its input starts.
+pub struct AlignedCodec<T> {
+    primitive: PrimitiveCodec<T>,
+    /// Whether the column is borrowed by `&[T]`, which requires it to actually be aligned.
+    borrowed: bool,
+}
+
+impl<T: Primitive> AlignedCodec<T> {
+    pub fn new(borrowed: bool) -> Self {
+        debug_assert!(align_of::<T>() <= MAX_ALIGN);
+        Self {
+            primitive: PrimitiveCodec::default(),
+            borrowed,
+        }
+    }
+
+    fn pad(out: &mut Output) {
+        let start = out.position() + 1;
+        let padding = start.next_multiple_of(align_of::<T>()) - start;
+        out.push(padding as u8);
+        out.extend_zeros(padding);
+    }
+
+    /// Safety: `input` must have been validated.
+    unsafe fn skip_padding(input: &mut &[u8]) {
+        let padding = consume_byte_arrays_unchecked(input, 1, 1)[0];
+        consume_byte_arrays_unchecked(input, padding as usize, 1);
+    }
+}
+
+impl<T: Primitive> Encoder for AlignedCodec<T> {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        Self::pad(out);
+        self.primitive.encode_one(erased, out);
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        Self::pad(out);
+        self.primitive.encode_many(erased, out);
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        Self::pad(out);
+        self.primitive.encode_many_strided(erased, stride, out);
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, _: bool) -> usize {
+        (out_len + 1).next_multiple_of(align_of::<T>()) + values.len() * size_of::<T>()
+    }
+}
+
+impl<T: Primitive> Decoder for AlignedCodec<T> {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        let padding = consume_byte_arrays(input, 1, 1)?[0] as usize;
+        if padding >= align_of::<T>() {
+            return err("invalid padding");
+        }
+        consume_byte_arrays(input, padding, 1)?;
+        if self.borrowed && !input.as_ptr().cast::<T>().is_aligned() {
+            return err("cannot borrow &[T] from unaligned input");
+        }
+        self.primitive.validate(input, length)
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        Self::skip_padding(input);
+        self.primitive.decode_one(input, erased);
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        Self::skip_padding(input);
+        self.primitive.decode_many(input, erased);
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        Self::skip_padding(input);
+        self.primitive.decode_many_strided(input, erased, stride);
+    }
+
+    fn can_decode_into(&self) -> bool {
+        true
+    }
+
+    unsafe fn decode_borrowed(&self, input: &mut &[u8], length: usize) -> *const u8 {
+        Self::skip_padding(input);
+        self.primitive.decode_borrowed(input, length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::benches::Vertex;
+    use crate::{deserialize_with, EncodeOptions};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::fmt::Debug;
+    use facet::Facet;
+
+    #[test]
+    fn test_align() {
+        let options = EncodeOptions {
+            align: true,
+            ..Default::default()
+        };
+        #[derive(Debug, PartialEq, Facet)]
+        struct Mesh<'a> {
+            name: &'a str,
+            positions: &'a [f32],
+            indices: &'a [u32],
+            colors: &'a [u8],
+            ids: Vec<u64>,
+            c: char,
+        }
+        let mesh = Mesh {
+            name: "abc",
+            positions: &[1.0, 2.0, 3.0],
+            indices: &[0, 1, 2],
+            colors: &[255],
+            ids: vec![5, 6],
+            c: 'x',
+        };
+        let bytes = crate::serialize_with(&mesh, options);
+        let mut buffer = vec![0u64; bytes.len() / 8 + 1];
+        let aligned = &mut bytemuck::cast_slice_mut::<u64, u8>(&mut buffer)[..bytes.len()];
+        aligned.copy_from_slice(&bytes);
+        let decoded = deserialize_with::<Mesh>(aligned, options).unwrap();
+        assert_eq!(decoded, mesh);
+        assert!(aligned
+            .as_ptr_range()
+            .contains(&decoded.positions.as_ptr().cast()));
+        assert!(aligned
+            .as_ptr_range()
+            .contains(&decoded.indices.as_ptr().cast()));
+
+        let unaligned = &mut bytemuck::cast_slice_mut::<u64, u8>(&mut buffer)[1..bytes.len() + 1];
+        unaligned.copy_from_slice(&bytes);
+        assert!(deserialize_with::<Mesh>(unaligned, options).is_err());
+
+        // Owned values can be decoded from unaligned input.
+        let vertices = || (0..10).map(Vertex::new).collect::<Vec<_>>();
+        let bytes = crate::serialize_with(&(1u8, vertices()), options);
+        assert_eq!(
+            deserialize_with::<(u8, Vec<Vertex>)>(&bytes, options).unwrap(),
+            (1, vertices())
+        );
+        assert_eq!(
+            crate::serialize_with(&(1u8, 2u32), options),
+            [1, 2, 0, 0, 2, 0, 0, 0]
+        );
+        assert!(deserialize_with::<u32>(&[4, 0, 0, 0, 0, 1, 0, 0, 0], options).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::error::{err, Result};
+use crate::scratch::Scratch;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::cell::{Cell, RefCell};
+use facet_core::Facet;
+
+/// Size of the first chunk, later chunks double in size.
+const MIN_CHUNK_SIZE: usize = 4096;
+/// Alignment of chunks, over-aligned values get their own chunk.
+const CHUNK_ALIGN: usize = 16;
+
+/// Bump allocator that [`deserialize_in`] allocates `&[T]` from. Decoding a message
+/// then costs a single allocation once the arena has grown to fit it, and all of it is freed at
+/// once when the arena is dropped or [`reset`](Arena::reset).
+///
+/// Values in the arena aren't dropped, so elements which own memory (e.g. `&[Vec<u8>]`) leak it.
+pub struct Arena {
+    chunks: RefCell<Vec<Scratch>>,
+    /// Unused part of the last chunk.
+    free: Cell<(*mut u8, usize)>,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self {
+            chunks: RefCell::new(Vec::new()),
+            free: Cell::new((core::ptr::null_mut(), 0)),
+        }
+    }
+
+    /// Creates an arena which can allocate `capacity` bytes (of alignment 16 or less) before
+    /// allocating again.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let arena = Self::new();
+        if capacity != 0 {
+            arena.add_chunk(capacity, CHUNK_ALIGN);
+        }
+        arena
+    }
+
+    /// Frees everything allocated so far, keeping the largest chunk to reuse.
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        if let Some(largest) = chunks.pop() {
+            chunks.clear();
+            self.free.set((largest.as_ptr(), largest.size()));
+            chunks.push(largest);
+        }
+    }
+
+    /// Allocates uninitialized space for `layout`.
+    pub(crate) fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() == 0 {
+            return layout.align() as *mut u8; // Dangling but aligned.
+        }
+        let (ptr, len) = self.free.get();
+        let padding = (ptr as usize).next_multiple_of(layout.align()) - ptr as usize;
+        if let Some(remaining) = len.checked_sub(padding + layout.size()) {
+            // Safety: `padding + layout.size()` bytes are free.
+            let allocation = unsafe { ptr.add(padding) };
+            self.free
+                .set((unsafe { allocation.add(layout.size()) }, remaining));
+            return allocation;
+        }
+        self.alloc_chunk(layout)
+    }
+
+    #[cold]
+    fn alloc_chunk(&self, layout: Layout) -> *mut u8 {
+        let last = self.chunks.borrow().last().map_or(0, |c| c.size());
+        let size = layout.size().max(last * 2).max(MIN_CHUNK_SIZE);
+        self.add_chunk(size, layout.align().max(CHUNK_ALIGN));
+        self.alloc(layout)
+    }
+
+    fn add_chunk(&self, size: usize, align: usize) {
+        let chunk = Scratch::new(Layout::from_size_align(size, align).unwrap(), 1);
+        self.free.set((chunk.as_ptr(), size));
+        self.chunks.borrow_mut().push(chunk);
+    }
+}
+
+std::thread_local! {
+    /// The arena of the [`deserialize_in`] running on this thread.
+    static CURRENT: Cell<*const Arena> = const { Cell::new(core::ptr::null()) };
+}
+
+/// Allocates `layout` from the arena of the [`deserialize_in`] running on this thread.
+///
+/// Panics if there isn't one (validating checks for it first, see [`check_current`]).
+pub fn alloc_current(layout: Layout) -> *mut u8 {
+    let current = CURRENT.get();
+    assert!(!current.is_null(), "no current Arena");
+    // Safety: `deserialize_in` borrows the arena while it's current.
+    unsafe { &*current }.alloc(layout)
+}
+
+/// Returns an error if there's no arena to decode `&[T]` into.
+pub fn check_current() -> Result<()> {
+    if CURRENT.get().is_null() {
+        return err("cannot deserialize &[T] without an Arena, see deserialize_in");
+    }
+    Ok(())
+}
+
+/// Like [`deserialize`](crate::deserialize), but `&[T]` (other than `&[u8]`, which borrows from
+/// `bytes`) is allocated from `arena`.
+pub fn deserialize_in<'a, T: Facet<'a>>(bytes: &'a [u8], arena: &'a Arena) -> Result<T> {
+    /// Restores the previous arena, even if decoding panics.
+    struct Restore(*const Arena);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.set(self.0);
+        }
+    }
+    let _restore = Restore(CURRENT.replace(arena));
+    crate::deserialize(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use core::fmt::Debug;
+    use facet::Facet;
+
+    #[test]
+    fn test_arena() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct Message<'a> {
+            name: &'a str,
+            data: &'a [u32],
+            chunks: &'a [&'a [u8]],
+            tags: Vec<&'a str>,
+        }
+        let message = Message {
+            name: "héllo",
+            data: &[1, 2, 3],
+            chunks: &[b"a", b"", b"bcd"],
+            tags: vec!["", "x", "yz"],
+        };
+        let bytes = crate::serialize(&message);
+
+        let mut arena = crate::Arena::with_capacity(16);
+        for _ in 0..3 {
+            let decoded = crate::deserialize_in::<Message>(&bytes, &arena).unwrap();
+            assert_eq!(decoded, message);
+            assert_eq!(decoded.data.as_ptr() as usize % align_of::<u32>(), 0);
+            arena.reset();
+        }
+
+        let mut invalid = bytes.clone();
+        let i = invalid
+            .iter()
+            .position(|&b| b == "é".as_bytes()[0])
+            .unwrap();
+        invalid[i] = 0xFF;
+        assert!(crate::deserialize_in::<Message>(&invalid, &arena).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::float::FloatEncoding;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::any::TypeId;
+#[cfg(feature = "std")]
+use facet_core::{Facet, Type, UserType};
+use facet_core::{Field, FieldAttribute, Shape};
+
+/// Field attributes of the form `#[facet(bitcode(float = xor, quantize(bits = 16, ...), shared, sparse))]`.
+///
+/// Attributes on a field also apply to the elements of containers such as `Vec<T>`. Fields of types
+/// which can't be annotated can be given attributes with [`register_field_attributes`].
+#[derive(Clone, Debug, Default)]
+pub struct FieldAttributes {
+    pub float: Option<FloatEncoding>,
+    /// Write each distinct `Arc<T>`/`Rc<T>` allocation once per message, including allocations
+    /// shared with other `shared` fields of the same pointer type. Requires `std`.
+    pub shared: bool,
+    /// Elide default values of the field (doesn't apply to elements of containers).
+    pub sparse: bool,
+}
+
+impl FieldAttributes {
+    /// Parses the attributes of `field` of `parent`, followed by those registered for it.
+    pub fn of(_parent: &Shape, field: &Field) -> Self {
+        let mut ret = Self::default();
+        for FieldAttribute::Arbitrary(s) in field.attributes {
+            ret.parse(s);
+        }
+        #[cfg(feature = "std")]
+        for &(id, name, attributes) in REGISTERED.read().unwrap().iter() {
+            if id == _parent.id.get() && name == field.name {
+                ret.parse(attributes);
+            }
+        }
+        ret
+    }
+
+    /// Parses one attribute, ignoring it unless it's `bitcode(...)`.
+    fn parse(&mut self, s: &str) {
+        let s: alloc::string::String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let Some(items) = s.strip_prefix("bitcode(").and_then(|s| s.strip_suffix(')')) else {
+            return;
+        };
+        for item in Item::parse_list(items) {
+            match (item.name, item.value) {
+                ("float", Some("xor")) => self.float = Some(FloatEncoding::Xor),
+                ("float", Some("auto")) => self.float = Some(FloatEncoding::Auto),
+                ("float", Some("shuffle")) => self.float = Some(FloatEncoding::Shuffle),
+                ("shared", None) if item.args.is_empty() => self.shared = true,
+                ("sparse", None) if item.args.is_empty() => self.sparse = true,
+                ("quantize", None) => {
+                    let (mut bits, mut min, mut max) = (None, None, None);
+                    for arg in &item.args {
+                        match (arg.name, arg.value) {
+                            ("bits", Some(v)) => bits = v.parse().ok(),
+                            ("min", Some(v)) => min = v.parse().ok(),
+                            ("max", Some(v)) => max = v.parse().ok(),
+                            _ => panic!("unknown quantize argument {}", arg.source),
+                        }
+                    }
+                    let (Some(bits), Some(min), Some(max)) = (bits, min, max) else {
+                        panic!("expected quantize(bits = .., min = .., max = ..)");
+                    };
+                    self.float = Some(FloatEncoding::quantize(bits, min, max));
+                }
+                _ => panic!("unknown attribute #[facet(bitcode({}))]", item.source),
+            }
+        }
+    }
+}
+
+/// Attributes registered with [`register_field_attributes`]: type, field name and attribute.
+#[cfg(feature = "std")]
+static REGISTERED: std::sync::RwLock<Vec<(TypeId, &'static str, &'static str)>> =
+    std::sync::RwLock::new(Vec::new());
+
+/// Gives the field named `field` of `T` (or of its enum variants) an attribute as if it were
+/// written `#[facet(<attribute>)]`, for types which can't be annotated (e.g. from other crates),
+/// such as `register_field_attributes::<Vertex>("position", "bitcode(quantize(bits = 16, ...))")`.
+/// Must be called before `T` is serialized or deserialized, since its codec is cached after.
+///
+/// Panics if `T` has no such field or the attribute is invalid.
+#[cfg(feature = "std")]
+pub fn register_field_attributes<T: for<'a> Facet<'a>>(
+    field: &'static str,
+    attribute: &'static str,
+) {
+    let shape = T::SHAPE;
+    let has_field = match shape.ty {
+        Type::User(UserType::Struct(t)) => t.fields.iter().any(|f| f.name == field),
+        Type::User(UserType::Enum(t)) => {
+            (t.variants.iter()).any(|variant| variant.data.fields.iter().any(|f| f.name == field))
+        }
+        _ => false,
+    };
+    assert!(has_field, "{shape} has no field {field}");
+    assert!(
+        attribute.trim_start().starts_with("bitcode"),
+        "expected bitcode(...), not {attribute}"
+    );
+    FieldAttributes::default().parse(attribute);
+    (REGISTERED.write().unwrap()).push((shape.id.get(), field, attribute));
+}
+
+/// `name`, `name = value` or `name(args, ...)`.
+struct Item<'a> {
+    source: &'a str,
+    name: &'a str,
+    value: Option<&'a str>,
+    args: Vec<Item<'a>>,
+}
+
+impl<'a> Item<'a> {
+    /// Parses a comma separated list of items without whitespace.
+    fn parse_list(s: &'a str) -> Vec<Self> {
+        let mut items = Vec::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.checked_sub(1).expect("unbalanced parentheses"),
+                ',' if depth == 0 => {
+                    items.push(Self::parse(&s[start..i]));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if start < s.len() {
+            items.push(Self::parse(&s[start..]));
+        }
+        items
+    }
+
+    fn parse(source: &'a str) -> Self {
+        let (name, value, args) = match source.find(['(', '=']) {
+            Some(i) if source.as_bytes()[i] == b'=' => (
+                &source[..i],
+                Some(source[i + 1..].trim_matches('"')),
+                Vec::new(),
+            ),
+            Some(i) => {
+                let args = source[i + 1..]
+                    .strip_suffix(')')
+                    .expect("unbalanced parentheses");
+                (&source[..i], None, Self::parse_list(args))
+            }
+            None => (source, None, Vec::new()),
+        };
+        Self {
+            source,
+            name,
+            value,
+            args,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::register_field_attributes;
+    use crate::{deserialize, serialize};
+    use alloc::vec::Vec;
+    use facet::Facet;
+
+    #[test]
+    fn test_register_field_attributes() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct Vertex {
+            x: f32,
+            id: u8,
+        }
+        #[derive(Debug, PartialEq, Facet)]
+        struct Unregistered {
+            x: f32,
+            id: u8,
+        }
+        register_field_attributes::<Vertex>("x", "bitcode(quantize(bits = 8, min = 0, max = 1))");
+
+        let vertices: Vec<_> = (0..10)
+            .map(|i| Vertex {
+                x: i as f32 / 10.0,
+                id: i,
+            })
+            .collect();
+        let bytes = serialize(&vertices);
+        assert_eq!(bytes.len(), 4 + (2 + 10) + 10);
+        let decoded: Vec<Vertex> = deserialize(&bytes).unwrap();
+        for (v, d) in vertices.iter().zip(&decoded) {
+            assert!((v.x - d.x).abs() <= 1.0 / 255.0, "{v:?} {d:?}");
+            assert_eq!(v.id, d.id);
+        }
+        // Only applies to Vertex.
+        assert!(deserialize::<Vec<Unregistered>>(&bytes).is_err());
+    }
+
+    #[test]
+    #[should_panic = "has no field"]
+    fn test_register_unknown_field() {
+        #[derive(Facet)]
+        struct Vertex {
+            x: f32,
+        }
+        register_field_attributes::<Vertex>("y", "bitcode(float = xor)");
+    }
+}
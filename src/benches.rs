@@ -4,10 +4,12 @@ use test::{black_box, Bencher};
 mod log;
 use log::{log_1k, log_one, Log};
 mod mesh;
-pub use mesh::Vertex;
-use mesh::{mesh_1k, mesh_one, Mesh};
+use mesh::{mesh_1k, mesh_1k_shuffled, mesh_one, Mesh, ShuffledMesh};
+pub use mesh::{ShuffledVertex, Vertex};
 mod nested;
 use nested::{struct_tree, T0};
+mod wide;
+use wide::{wide_1k, wide_64k, WideTable};
 
 macro_rules! bench {
     ($($b:ident: $t:ty),+) => { $(mod $b { use super::*;
@@ -126,4 +128,4 @@ macro_rules! bench {
         }
     }
 })+}}
-bench!(mesh_one: Mesh, mesh_1k: Mesh, log_one: Log, log_1k: Log, struct_tree: T0);
+bench!(mesh_one: Mesh, mesh_1k: Mesh, mesh_1k_shuffled: ShuffledMesh, log_one: Log, log_1k: Log, struct_tree: T0, wide_1k: WideTable, wide_64k: WideTable);
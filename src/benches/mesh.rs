@@ -25,7 +25,29 @@ impl Vertex {
     }
 }
 
+/// [`Vertex`] with its float columns split into byte planes.
+#[derive(Debug, PartialEq, Facet, Serialize, Deserialize, bitcode::Encode, bitcode::Decode)]
+pub struct ShuffledVertex {
+    #[facet(bitcode(float = shuffle))]
+    x: f32,
+    #[facet(bitcode(float = shuffle))]
+    y: f32,
+    #[facet(bitcode(float = shuffle))]
+    z: f32,
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+impl From<Vertex> for ShuffledVertex {
+    fn from(v: Vertex) -> Self {
+        let Vertex { x, y, z, r, g, b } = v;
+        Self { x, y, z, r, g, b }
+    }
+}
+
 pub type Mesh = Vec<Vertex>;
+pub type ShuffledMesh = Vec<ShuffledVertex>;
 
 fn mesh(n: usize) -> Mesh {
     (0..n).map(Vertex::new).collect()
@@ -38,3 +60,7 @@ pub fn mesh_one() -> Mesh {
 pub fn mesh_1k() -> Mesh {
     mesh(1000)
 }
+
+pub fn mesh_1k_shuffled() -> ShuffledMesh {
+    mesh(1000).into_iter().map(Into::into).collect()
+}
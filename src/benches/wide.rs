@@ -0,0 +1,90 @@
+use alloc::vec::Vec;
+use facet::Facet;
+use serde::{Deserialize, Serialize};
+
+/// Many small fields, which bottleneck on memory bandwidth when encoded one column at a time.
+#[derive(Debug, PartialEq, Facet, Serialize, Deserialize, bitcode::Encode, bitcode::Decode)]
+pub struct Wide {
+    f0: u8,
+    f1: u8,
+    f2: u8,
+    f3: u8,
+    f4: u8,
+    f5: u8,
+    f6: u8,
+    f7: u8,
+    f8: u8,
+    f9: u8,
+    f10: u8,
+    f11: u8,
+    f12: u8,
+    f13: u8,
+    f14: u8,
+    f15: u8,
+    f16: u8,
+    f17: u8,
+    f18: u8,
+    f19: u8,
+    f20: u8,
+    f21: u8,
+    f22: u8,
+    f23: u8,
+    f24: u8,
+    f25: u8,
+    f26: u8,
+    f27: u8,
+    f28: u8,
+    f29: u8,
+    f30: u8,
+    f31: u8,
+}
+
+impl Wide {
+    fn new(i: usize) -> Self {
+        Self {
+            f0: i as u8,
+            f1: (i + 1) as u8,
+            f2: (i + 2) as u8,
+            f3: (i + 3) as u8,
+            f4: (i + 4) as u8,
+            f5: (i + 5) as u8,
+            f6: (i + 6) as u8,
+            f7: (i + 7) as u8,
+            f8: (i + 8) as u8,
+            f9: (i + 9) as u8,
+            f10: (i + 10) as u8,
+            f11: (i + 11) as u8,
+            f12: (i + 12) as u8,
+            f13: (i + 13) as u8,
+            f14: (i + 14) as u8,
+            f15: (i + 15) as u8,
+            f16: (i + 16) as u8,
+            f17: (i + 17) as u8,
+            f18: (i + 18) as u8,
+            f19: (i + 19) as u8,
+            f20: (i + 20) as u8,
+            f21: (i + 21) as u8,
+            f22: (i + 22) as u8,
+            f23: (i + 23) as u8,
+            f24: (i + 24) as u8,
+            f25: (i + 25) as u8,
+            f26: (i + 26) as u8,
+            f27: (i + 27) as u8,
+            f28: (i + 28) as u8,
+            f29: (i + 29) as u8,
+            f30: (i + 30) as u8,
+            f31: (i + 31) as u8,
+        }
+    }
+}
+
+pub type WideTable = Vec<Wide>;
+
+pub fn wide_1k() -> WideTable {
+    (0..1000).map(Wide::new).collect()
+}
+
+/// Large enough (2 MiB) to be transposed a tile at a time.
+pub fn wide_64k() -> WideTable {
+    (0..1 << 16).map(Wide::new).collect()
+}
@@ -0,0 +1,118 @@
+use crate::error::{err_eof, Result};
+use crate::output::Output;
+
+/// Mask of the lowest `n` bits, `n` can be 64.
+#[inline(always)]
+pub fn mask(n: u32) -> u64 {
+    if n >= 64 {
+        u64::MAX
+    } else {
+        (1 << n) - 1
+    }
+}
+
+/// Packs bits least significant first into whole bytes appended to `out`.
+pub struct BitWriter<'a, 'b> {
+    out: &'a mut Output<'b>,
+    buf: u128,
+    len: u32,
+}
+
+impl<'a, 'b> BitWriter<'a, 'b> {
+    pub fn new(out: &'a mut Output<'b>) -> Self {
+        Self {
+            out,
+            buf: 0,
+            len: 0,
+        }
+    }
+
+    /// Writes the lowest `n` bits of `bits` (the rest must be zero).
+    #[inline(always)]
+    pub fn write(&mut self, bits: u64, n: u32) {
+        debug_assert!(n <= 64);
+        debug_assert_eq!(bits & !mask(n), 0);
+        self.buf |= (bits as u128) << self.len;
+        self.len += n;
+        if self.len >= 64 {
+            self.out.extend_from_slice(&(self.buf as u64).to_le_bytes());
+            self.buf >>= 64;
+            self.len -= 64;
+        }
+    }
+
+    /// Flushes the remaining bits padded with zeros to a whole byte.
+    pub fn finish(self) {
+        let bytes = (self.buf as u64).to_le_bytes();
+        self.out
+            .extend_from_slice(&bytes[..self.len.div_ceil(8) as usize]);
+    }
+}
+
+/// Reads bits written by [`BitWriter`].
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// Reads `n` bits or returns an error if there aren't enough bytes.
+    #[inline(always)]
+    pub fn read(&mut self, n: u32) -> Result<u64> {
+        if (self.bytes.len() * 8 - self.pos) < n as usize {
+            return err_eof(self.bytes, (self.pos + n as usize).div_ceil(8));
+        }
+        // Safety: checked that `n` bits are remaining above.
+        Ok(unsafe { self.read_unchecked(n) })
+    }
+
+    /// Safety: `n` bits must be remaining (which [`Self::read`] checked during validation).
+    #[inline(always)]
+    pub unsafe fn read_unchecked(&mut self, n: u32) -> u64 {
+        debug_assert!(n <= 64);
+        debug_assert!(self.bytes.len() * 8 - self.pos >= n as usize);
+        let start = self.pos / 8;
+        let end = (self.pos + n as usize).div_ceil(8);
+        let mut buf = 0u128;
+        for (i, &b) in self.bytes.get_unchecked(start..end).iter().enumerate() {
+            buf |= (b as u128) << (i * 8);
+        }
+        let shift = self.pos % 8;
+        self.pos += n as usize;
+        ((buf >> shift) as u64) & mask(n)
+    }
+
+    /// Returns the next `n` bits without reading them, padded with zeros past the end.
+    #[cfg(feature = "compress")]
+    #[inline(always)]
+    pub fn peek(&self, n: u32) -> u64 {
+        debug_assert!(n <= 64);
+        let start = (self.pos / 8).min(self.bytes.len());
+        let end = (self.pos + n as usize).div_ceil(8).min(self.bytes.len());
+        let mut buf = 0u128;
+        for (i, &b) in self.bytes[start..end].iter().enumerate() {
+            buf |= (b as u128) << (i * 8);
+        }
+        ((buf >> (self.pos % 8)) as u64) & mask(n)
+    }
+
+    /// Skips `n` bits or returns an error if there aren't enough bytes.
+    #[cfg(feature = "compress")]
+    #[inline(always)]
+    pub fn skip(&mut self, n: u32) -> Result<()> {
+        if (self.bytes.len() * 8 - self.pos) < n as usize {
+            return err_eof(self.bytes, (self.pos + n as usize).div_ceil(8));
+        }
+        self.pos += n as usize;
+        Ok(())
+    }
+
+    /// Number of whole bytes that contain bits which have been read.
+    pub fn bytes_read(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+}
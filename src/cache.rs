@@ -1,4 +1,5 @@
 use crate::codec::Codec;
+use crate::options::{EncodeOptions, Optimize};
 use facet_core::Shape;
 pub use fast::reflect;
 
@@ -10,65 +11,200 @@ mod fast {
     use core::any::TypeId;
     use core::cell::Cell;
 
+    /// Enough for a handful of types serialized in a loop, small enough to scan on a miss.
+    const WAYS: usize = 8;
+
+    type Entry = (TypeId, EncodeOptions, StaticCodec);
+
     struct _Dummy;
+    const EMPTY: Entry = (
+        TypeId::of::<_Dummy>(),
+        EncodeOptions {
+            optimize: Optimize::Speed,
+            align: false,
+        },
+        &DUMMY_CODEC,
+    );
     thread_local! {
-        static FAST_CACHE: Cell<(TypeId, StaticCodec)> = Cell::new((TypeId::of::<_Dummy>(), &DUMMY_CODEC));
+        /// Most recently used first.
+        static FAST_CACHE: [Cell<Entry>; WAYS] = const { [const { Cell::new(EMPTY) }; WAYS] };
     }
 
     // Saves 3ns over shared cache in benchmark with 0 contention.
     #[inline(always)]
-    pub fn reflect(shape: &'static Shape) -> StaticCodec {
+    pub fn reflect(shape: &'static Shape, options: EncodeOptions) -> StaticCodec {
         let shape_id = shape.id.get();
-        let (id, cached) = FAST_CACHE.get();
-        if id == shape_id {
-            cached
-        } else {
-            cache_miss(shape)
-        }
+        FAST_CACHE.with(|cache| {
+            let (id, cached_options, cached) = cache[0].get();
+            if id == shape_id && cached_options == options {
+                cached
+            } else {
+                cache_miss(cache, shape, options)
+            }
+        })
     }
 
+    /// Checks the other entries, swapping a hit to the front. Otherwise gets the codec from the
+    /// shared cache and inserts it at the front, evicting the last entry.
     #[cold]
-    fn cache_miss(shape: &'static Shape) -> StaticCodec {
-        let codec = super::shared::reflect(shape);
-        FAST_CACHE.set((shape.id.get(), codec));
+    fn cache_miss(
+        cache: &[Cell<Entry>; WAYS],
+        shape: &'static Shape,
+        options: EncodeOptions,
+    ) -> StaticCodec {
+        let shape_id = shape.id.get();
+        for i in 1..WAYS {
+            let (id, cached_options, cached) = cache[i].get();
+            if id == shape_id && cached_options == options {
+                cache[0].swap(&cache[i]);
+                return cached;
+            }
+        }
+        let codec = super::shared::reflect(shape, options);
+        for i in (1..WAYS).rev() {
+            cache[i].set(cache[i - 1].get());
+        }
+        cache[0].set((shape_id, options, codec));
         codec
     }
 }
 
+/// Append-only lock-free hash table. Reads never wait and codecs are constructed without holding
+/// a lock, so a panicking construction doesn't poison the cache.
 mod shared {
     use super::*;
     use core::any::TypeId;
-    use std::sync::{PoisonError, RwLock};
+    use core::hash::{Hash, Hasher};
+    use core::sync::atomic::{AtomicPtr, Ordering};
+    use std::hash::DefaultHasher;
 
-    static SHARED_CACHE: RwLock<Vec<(TypeId, StaticCodec)>> = RwLock::new(vec![]);
+    type Key = (TypeId, EncodeOptions);
 
-    pub fn reflect(shape: &'static Shape) -> StaticCodec {
-        let shape_id = shape.id.get();
-        if let Ok(codec) = entry_or_insert_index(
-            &SHARED_CACHE.read().unwrap_or_else(PoisonError::into_inner),
-            shape_id,
-        ) {
+    const BUCKETS: usize = 64;
+
+    /// Nodes are leaked like the codecs, so references to them are always valid.
+    struct Node {
+        key: Key,
+        codec: StaticCodec,
+        next: *const Node,
+    }
+
+    static SHARED_CACHE: [AtomicPtr<Node>; BUCKETS] =
+        [const { AtomicPtr::new(core::ptr::null_mut()) }; BUCKETS];
+
+    pub fn reflect(shape: &'static Shape, options: EncodeOptions) -> StaticCodec {
+        let key = (shape.id.get(), options);
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let bucket = &SHARED_CACHE[hasher.finish() as usize % BUCKETS];
+
+        let mut head = bucket.load(Ordering::Acquire);
+        if let Some(codec) = find(head, key, core::ptr::null()) {
             return codec;
         }
 
-        let mut write_cache = SHARED_CACHE.write().unwrap_or_else(PoisonError::into_inner);
-        match entry_or_insert_index(&write_cache, shape_id) {
-            Ok(codec) => codec,
-            Err(i) => {
-                let codec = Box::leak(crate::codec::reflect(shape));
-                write_cache.insert(i, (shape_id, codec));
-                codec
+        // Another thread may construct the same codec at the same time, but only one is inserted.
+        let node = Box::into_raw(Box::new(Node {
+            key,
+            codec: Box::leak(crate::codec::reflect(shape, options)),
+            next: head,
+        }));
+        loop {
+            match bucket.compare_exchange_weak(head, node, Ordering::AcqRel, Ordering::Acquire) {
+                // Safety: `node` is leaked now.
+                Ok(_) => return unsafe { (*node).codec },
+                Err(new_head) => {
+                    // Only check nodes inserted since the last check.
+                    if let Some(codec) = find(new_head, key, head) {
+                        // Safety: `node` and its codec weren't published.
+                        unsafe {
+                            let node = Box::from_raw(node);
+                            drop(Box::from_raw(
+                                node.codec as *const dyn Codec as *mut dyn Codec,
+                            ));
+                        }
+                        return codec;
+                    }
+                    head = new_head;
+                    // Safety: `node` isn't published yet.
+                    unsafe { (*node).next = head };
+                }
             }
         }
     }
 
-    #[inline(never)]
-    fn entry_or_insert_index(
-        cache: &[(TypeId, StaticCodec)],
-        shape_id: TypeId,
-    ) -> Result<StaticCodec, usize> {
-        cache
-            .binary_search_by_key(&shape_id, |(id, _)| *id)
-            .map(|i| cache[i].1)
+    /// Searches the nodes from `head` until `end`.
+    fn find(mut head: *const Node, key: Key, end: *const Node) -> Option<StaticCodec> {
+        while head != end {
+            // Safety: nodes are leaked and were published with release ordering.
+            let node = unsafe { &*head };
+            if node.key == key {
+                return Some(node.codec);
+            }
+            head = node.next;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::benches::Vertex;
+    use alloc::vec;
+    use core::fmt::Debug;
+    use facet::Facet;
+
+    fn roundtrip<T: for<'facet> Facet<'facet> + Debug + PartialEq>(t: &T) {
+        let bytes = crate::serialize(t);
+        assert_eq!(crate::deserialize::<T>(&bytes).unwrap(), *t);
+    }
+
+    #[test]
+    fn test_cache_many_types() {
+        // More types than the thread local cache holds, in a loop.
+        for i in 0..3 {
+            roundtrip(&(i as u8));
+            roundtrip(&(i as u16));
+            roundtrip(&(i as u32));
+            roundtrip(&(i as u64));
+            roundtrip(&(i as i8));
+            roundtrip(&(i as i16));
+            roundtrip(&(i as i32));
+            roundtrip(&(i as i64));
+            roundtrip(&(i as f32));
+            roundtrip(&(i as f64));
+            roundtrip(&Vertex::new(i));
+            roundtrip(&vec![Vertex::new(i)]);
+        }
+    }
+
+    #[test]
+    fn test_cache_concurrent() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct A(u8, u16);
+        #[derive(Debug, PartialEq, Facet)]
+        struct B(u16, u32);
+        #[derive(Debug, PartialEq, Facet)]
+        struct C(u32, u64);
+
+        std::thread::scope(|s| {
+            for i in 0..8 {
+                s.spawn(move || {
+                    roundtrip(&vec![A(i, 1)]);
+                    roundtrip(&vec![B(1, i as u32)]);
+                    roundtrip(&vec![C(i as u32, 1)]);
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_cache_panic() {
+        // Arrays aren't supported yet, so constructing their codec panics.
+        let panic = || std::panic::catch_unwind(|| crate::serialize(&[0u8; 3]));
+        assert!(panic().is_err());
+        assert!(panic().is_err());
+        // The cache still works.
+        roundtrip(&[1u8, 2, 3].to_vec());
     }
 }
@@ -0,0 +1,267 @@
+use crate::codec::Codec;
+use crate::consume::expect_eof;
+use crate::options::EncodeOptions;
+use crate::output::Output;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use facet_core::Facet;
+use std::io::{self, Read, Write};
+
+/// Each chunk starts with its number of elements (a `u32`, 0 ends the stream) followed by its size
+/// in bytes (a `u64`).
+const COUNT_SIZE: usize = 4;
+const SIZE_SIZE: usize = 8;
+
+/// Writes a sequence of `T` to `W` in chunks of up to `chunk_len` elements, each of which is
+/// encoded in columns on its own, so only one chunk is buffered at a time. Read it with
+/// [`ChunkReader`].
+///
+/// The end of the stream is only written by [`ChunkWriter::finish`]. Dropping the writer without
+/// calling it discards the buffered elements and leaves the stream without its end, which a
+/// [`ChunkReader`] reports as [`io::ErrorKind::UnexpectedEof`].
+pub struct ChunkWriter<T, W> {
+    writer: W,
+    codec: &'static dyn Codec,
+    chunk_len: usize,
+    /// Elements pushed since the last chunk.
+    pending: Vec<T>,
+    /// Reused to encode each chunk.
+    buffer: Vec<u8>,
+}
+
+impl<'facet, T: Facet<'facet>, W: Write> ChunkWriter<T, W> {
+    /// Panics if `chunk_len` is 0 or larger than `u32::MAX`.
+    pub fn new(writer: W, chunk_len: usize) -> Self {
+        Self::with_options(writer, chunk_len, EncodeOptions::default())
+    }
+
+    /// Like [`ChunkWriter::new`] with [`EncodeOptions`].
+    pub fn with_options(writer: W, chunk_len: usize, options: EncodeOptions) -> Self {
+        assert!(
+            chunk_len != 0 && chunk_len <= u32::MAX as usize,
+            "chunk_len must be in 1..=u32::MAX"
+        );
+        Self {
+            writer,
+            codec: crate::reflect(T::SHAPE, options),
+            chunk_len,
+            pending: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Buffers `t`, writing a chunk once there are `chunk_len` buffered.
+    pub fn push(&mut self, t: T) -> io::Result<()> {
+        self.pending.push(t);
+        if self.pending.len() == self.chunk_len {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered elements followed by `ts` in chunks.
+    pub fn write(&mut self, ts: &[T]) -> io::Result<()> {
+        self.write_pending()?;
+        for chunk in ts.chunks(self.chunk_len) {
+            self.write_chunk(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered elements and the end of the stream, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_pending()?;
+        self.writer.write_all(&0u32.to_le_bytes())?;
+        Ok(self.writer)
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut pending = core::mem::take(&mut self.pending);
+        let result = self.write_chunk(&pending);
+        pending.clear();
+        self.pending = pending;
+        result
+    }
+
+    fn write_chunk(&mut self, chunk: &[T]) -> io::Result<()> {
+        self.buffer.clear();
+        let erased = core::ptr::slice_from_raw_parts(chunk.as_ptr() as *const u8, chunk.len());
+        // Safety: reflected from `T`.
+        unsafe { (self.codec).encode_many(erased, &mut Output::new(&mut self.buffer)) };
+
+        let mut header = [0; COUNT_SIZE + SIZE_SIZE];
+        header[..COUNT_SIZE].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+        header[COUNT_SIZE..].copy_from_slice(&(self.buffer.len() as u64).to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.buffer)
+    }
+}
+
+/// Reads the chunks written by [`ChunkWriter`] from `R` one at a time, validating each on its own.
+/// Iterating yields the chunks until the end of the stream.
+///
+/// Decoding errors are returned as [`io::ErrorKind::InvalidData`] wrapping an [`Error`].
+///
+/// [`Error`]: crate::Error
+pub struct ChunkReader<T, R> {
+    reader: R,
+    codec: &'static dyn Codec,
+    /// Reused to read each chunk.
+    buffer: Vec<u8>,
+    /// Whether the end of the stream or an error was reached.
+    done: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: for<'facet> Facet<'facet>, R: Read> ChunkReader<T, R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, EncodeOptions::default())
+    }
+
+    /// Like [`ChunkReader::new`] for chunks written with
+    /// [`ChunkWriter::with_options`] and the same [`EncodeOptions`].
+    pub fn with_options(reader: R, options: EncodeOptions) -> Self {
+        Self {
+            reader,
+            codec: crate::reflect(T::SHAPE, options),
+            buffer: Vec::new(),
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reads the next chunk, or returns `None` at the end of the stream.
+    pub fn read_chunk(&mut self) -> io::Result<Option<Vec<T>>> {
+        let mut chunk = Vec::new();
+        Ok(self.read_chunk_into(&mut chunk)?.then_some(chunk))
+    }
+
+    /// Like [`ChunkReader::read_chunk`], but replaces the elements of `chunk`, reusing its
+    /// allocation. Returns false at the end of the stream.
+    pub fn read_chunk_into(&mut self, chunk: &mut Vec<T>) -> io::Result<bool> {
+        chunk.clear();
+        let mut count = [0; COUNT_SIZE];
+        self.reader.read_exact(&mut count)?;
+        let count = u32::from_le_bytes(count) as usize;
+        if count == 0 {
+            return Ok(false);
+        }
+        let mut size = [0; SIZE_SIZE];
+        self.reader.read_exact(&mut size)?;
+        let size = u64::from_le_bytes(size);
+
+        // Grows with the input instead of trusting the size.
+        self.buffer.clear();
+        (&mut self.reader)
+            .take(size)
+            .read_to_end(&mut self.buffer)?;
+        if self.buffer.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut validated = self.buffer.as_slice();
+        self.codec
+            .validate(&mut validated, count)
+            .and_then(|()| expect_eof(validated))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        chunk.reserve(count);
+        let mut decoded = self.buffer.as_slice();
+        let erased = core::ptr::slice_from_raw_parts_mut(chunk.as_mut_ptr() as *mut u8, count);
+        // Safety: reflected from `T` and validated, `chunk` has space for `count` elements.
+        unsafe {
+            self.codec.decode_many(&mut decoded, erased);
+            chunk.set_len(count);
+        }
+        debug_assert_eq!(validated.len(), decoded.len());
+        Ok(true)
+    }
+}
+
+impl<T: for<'facet> Facet<'facet>, R: Read> Iterator for ChunkReader<T, R> {
+    type Item = io::Result<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_chunk().transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::fmt::Debug;
+    use facet::Facet;
+
+    #[test]
+    fn test_chunked() {
+        use crate::{ChunkReader, ChunkWriter};
+        use std::io::ErrorKind;
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Record {
+            id: u32,
+            ok: bool,
+            tags: Vec<u8>,
+        }
+        let record = |i: u32| Record {
+            id: i,
+            ok: i % 3 == 1,
+            tags: vec![i as u8; i as usize % 4],
+        };
+
+        let mut writer = ChunkWriter::new(Vec::new(), 3);
+        writer
+            .write(&(0..10).map(record).collect::<Vec<_>>())
+            .unwrap();
+        for i in 10..14 {
+            writer.push(record(i)).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let chunks: Vec<Vec<Record>> = ChunkReader::new(bytes.as_slice())
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        let lengths: Vec<_> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [3, 3, 3, 1, 3, 1]);
+        assert_eq!(
+            chunks.into_iter().flatten().collect::<Vec<_>>(),
+            (0..14).map(record).collect::<Vec<_>>()
+        );
+
+        let mut reader = ChunkReader::<Record, _>::new(bytes.as_slice());
+        let mut chunk = Vec::new();
+        assert!(reader.read_chunk_into(&mut chunk).unwrap());
+        let allocation = chunk.as_ptr();
+        assert!(reader.read_chunk_into(&mut chunk).unwrap());
+        assert_eq!(chunk.as_ptr(), allocation);
+        assert_eq!(chunk, (3..6).map(record).collect::<Vec<_>>());
+
+        // Chunks are validated on their own, so the ones before an invalid chunk are read.
+        let first_size = u64::from_le_bytes(bytes[4..12].try_into().unwrap()) as usize;
+        let second = 12 + first_size;
+        let mut invalid = bytes.clone();
+        invalid[second + 12 + 3 * 4] = 2; // The first bool of the second chunk.
+        let mut reader = ChunkReader::<Record, _>::new(invalid.as_slice());
+        assert!(reader.next().unwrap().is_ok());
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+
+        // A stream without its end is truncated.
+        let truncated = &bytes[..bytes.len() - 1];
+        let result: std::io::Result<Vec<Vec<Record>>> = ChunkReader::new(truncated).collect();
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        let truncated = &bytes[..second + 20];
+        let result: std::io::Result<Vec<Vec<Record>>> = ChunkReader::new(truncated).collect();
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+}
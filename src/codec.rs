@@ -1,67 +1,213 @@
+use crate::align::AlignedCodec;
+use crate::attribute::FieldAttributes;
+use crate::bits::mask;
 use crate::decoder::Decoder;
 use crate::encoder::Encoder;
-use crate::primitive::PrimitiveCodec;
-use crate::slice::{BoxedSliceCodec, BoxedSliceMarker, VecMarker};
+use crate::enum_::{EnumCodec, EnumVariant};
+use crate::float::{Float, FloatCodec};
+use crate::int::{Int, IntCodec};
+use crate::options::{EncodeOptions, Optimize};
+use crate::pointer::{PointerCodec, ScopedCodec};
+use crate::primitive::{Primitive, PrimitiveCodec};
+use crate::slice::{ArenaMarker, BoxedSliceCodec, InputMarker, VecMarker};
+use crate::sparse::{DefaultEq, SparseCodec};
 use crate::struct_::{StructCodec, StructField};
 use alloc::boxed::Box;
-use bytemuck::{CheckedBitPattern, NoUninit};
+use core::alloc::Layout;
+use core::cell::Cell;
 use facet_core::{
-    Def, KnownPointer, ListDef, NumericType, PointerDef, PointerType, PrimitiveType, SequenceType,
-    Shape, SliceType, TextualType, Type, UserType, ValuePointerType,
+    Def, EnumRepr, Field, KnownPointer, ListDef, NumericType, PointerDef, PointerType,
+    PrimitiveType, SequenceType, Shape, SliceType, TextualType, Type, UserType, ValuePointerType,
 };
 
 pub trait Codec: Encoder + Decoder {}
 impl<T: Encoder + Decoder> Codec for T {}
 pub type DynamicCodec = Box<dyn Codec>;
 
-fn primitive<T: NoUninit + CheckedBitPattern + Default>() -> DynamicCodec {
+fn primitive<T: Primitive>() -> DynamicCodec {
     Box::new(PrimitiveCodec::<T>::default())
 }
 
-pub fn reflect(shape: &Shape) -> DynamicCodec {
+/// A [`primitive`] which is padded to its alignment if [`EncodeOptions::align`].
+fn aligned<T: Primitive>(options: EncodeOptions) -> DynamicCodec {
+    if options.align && align_of::<T>() > 1 {
+        Box::new(AlignedCodec::<T>::new(false))
+    } else {
+        primitive::<T>()
+    }
+}
+
+fn int<T: Int>(options: EncodeOptions) -> DynamicCodec {
+    match options.optimize {
+        Optimize::Speed => aligned::<T>(options),
+        Optimize::Size => Box::new(IntCodec::<T>::default()),
+    }
+}
+
+fn float<T: Float>(attributes: &FieldAttributes, options: EncodeOptions) -> DynamicCodec {
+    match attributes.float {
+        Some(encoding) => Box::new(FloatCodec::<T>::new(encoding)),
+        None => aligned::<T>(options),
+    }
+}
+
+/// Codec of the elements of a `&[T]` which can be borrowed from the input: bytes, or primitives
+/// if [`EncodeOptions::align`].
+fn borrowed_elements(t: &Shape, options: EncodeOptions) -> Option<DynamicCodec> {
+    fn borrowed<T: Primitive>() -> Option<DynamicCodec> {
+        Some(Box::new(AlignedCodec::<T>::new(true)))
+    }
+    let Type::Primitive(PrimitiveType::Numeric(numeric)) = t.ty else {
+        return None;
+    };
+    match (numeric, t.layout.sized_layout().unwrap().size()) {
+        (NumericType::Integer { .. }, 1) => Some(primitive::<u8>()),
+        _ if !options.align => None,
+        (NumericType::Integer { signed: false }, 2) => borrowed::<u16>(),
+        (NumericType::Integer { signed: false }, 4) => borrowed::<u32>(),
+        (NumericType::Integer { signed: false }, 8) => borrowed::<u64>(),
+        (NumericType::Integer { signed: true }, 2) => borrowed::<i16>(),
+        (NumericType::Integer { signed: true }, 4) => borrowed::<i32>(),
+        (NumericType::Integer { signed: true }, 8) => borrowed::<i64>(),
+        (NumericType::Float, 4) => borrowed::<f32>(),
+        (NumericType::Float, 8) => borrowed::<f64>(),
+        _ => None,
+    }
+}
+
+fn sparse(shape: &Shape, values: DynamicCodec) -> DynamicCodec {
+    let vtable = shape.vtable.sized().unwrap();
+    let Some(default_in_place) = (vtable.default_in_place)() else {
+        panic!("#[facet(bitcode(sparse))] requires Default, which {shape} lacks");
+    };
+    let default_eq = match (shape.ty, (vtable.partial_eq)()) {
+        (Type::Primitive(PrimitiveType::Numeric(_) | PrimitiveType::Boolean), _) => {
+            DefaultEq::Bytes
+        }
+        (_, Some(partial_eq)) if !contains_float(shape) => DefaultEq::PartialEq(partial_eq),
+        _ => DefaultEq::Encoding,
+    };
+    Box::new(SparseCodec::new(
+        values,
+        shape.layout.sized_layout().unwrap(),
+        default_in_place,
+        (vtable.drop_in_place)(),
+        default_eq,
+    ))
+}
+
+/// Whether `shape` might contain floats, whose `PartialEq` doesn't distinguish `-0.0` and `0.0`.
+fn contains_float(shape: &Shape) -> bool {
+    let fields_contain_float = |fields: &[Field]| fields.iter().any(|f| contains_float(f.shape));
+    match (shape.ty, shape.def) {
+        (Type::Primitive(PrimitiveType::Numeric(NumericType::Float)), _) => true,
+        (Type::Primitive(_), _) => false,
+        (Type::User(UserType::Struct(t)), _) => fields_contain_float(t.fields),
+        (Type::User(UserType::Enum(t)), _) => {
+            (t.variants.iter()).any(|variant| fields_contain_float(variant.data.fields))
+        }
+        (_, Def::Scalar) => false,
+        (_, Def::List(ListDef { t, .. })) => contains_float(t()),
+        _ => true,
+    }
+}
+
+/// Reflects the fields of `parent`, a struct or enum.
+fn reflect_fields<'a>(
+    parent: &'a Shape,
+    fields: &'a [Field],
+    options: EncodeOptions,
+    shared: &'a Cell<bool>,
+) -> impl Iterator<Item = StructField> + 'a {
+    fields.iter().map(move |field| {
+        // TODO respect field.flags
+        let attributes = FieldAttributes::of(parent, field);
+        let mut codec = reflect_with_attributes(field.shape, &attributes, options, shared);
+        if attributes.sparse {
+            codec = sparse(field.shape, codec);
+        }
+        StructField::new(
+            codec,
+            field.offset,
+            field.shape.layout.sized_layout().unwrap().size(),
+        )
+    })
+}
+
+pub fn reflect(shape: &Shape, options: EncodeOptions) -> DynamicCodec {
+    let shared = Cell::new(false);
+    let codec = reflect_with_attributes(shape, &FieldAttributes::default(), options, &shared);
+    if shared.get() {
+        Box::new(ScopedCodec(codec))
+    } else {
+        codec
+    }
+}
+
+/// Sets `shared` if there are shared pointers, which need a [`ScopedCodec`].
+fn reflect_with_attributes(
+    shape: &Shape,
+    attributes: &FieldAttributes,
+    options: EncodeOptions,
+    shared: &Cell<bool>,
+) -> DynamicCodec {
+    let reflect = |shape| reflect_with_attributes(shape, attributes, options, shared);
     match shape.ty {
         Type::Primitive(PrimitiveType::Numeric(NumericType::Integer { signed: false })) => {
             match shape.layout.sized_layout().unwrap().size() {
-                1 => primitive::<u8>(),
-                2 => primitive::<u16>(),
-                4 => primitive::<u32>(),
-                8 => primitive::<u64>(),
+                1 => int::<u8>(options),
+                2 => int::<u16>(options),
+                4 => int::<u32>(options),
+                8 => int::<u64>(options),
                 // TODO detect usize.
                 _ => todo!("{shape:?}"),
             }
         }
         Type::Primitive(PrimitiveType::Numeric(NumericType::Integer { signed: true })) => {
             match shape.layout.sized_layout().unwrap().size() {
-                1 => primitive::<i8>(),
-                2 => primitive::<i16>(),
-                4 => primitive::<i32>(),
-                8 => primitive::<i64>(),
+                1 => int::<i8>(options),
+                2 => int::<i16>(options),
+                4 => int::<i32>(options),
+                8 => int::<i64>(options),
                 // TODO detect isize.
                 _ => todo!("{shape:?}"),
             }
         }
         Type::Primitive(PrimitiveType::Numeric(NumericType::Float)) => {
             match shape.layout.sized_layout().unwrap().size() {
-                4 => primitive::<f32>(),
-                8 => primitive::<f64>(),
+                4 => float::<f32>(attributes, options),
+                8 => float::<f64>(attributes, options),
                 _ => todo!("{shape:?}"),
             }
         }
         Type::Primitive(PrimitiveType::Boolean) => primitive::<bool>(),
-        Type::Primitive(PrimitiveType::Textual(TextualType::Char)) => primitive::<char>(),
+        Type::Primitive(PrimitiveType::Textual(TextualType::Char)) => aligned::<char>(options),
         // TODO(safety) packed struct
-        Type::User(UserType::Struct(t)) => {
-            StructCodec::new_dynamic(
-                t.fields.iter().map(|field| {
-                    // TODO respect field.flags
-                    StructField::new(
-                        reflect(field.shape),
-                        field.offset,
-                        field.shape.layout.sized_layout().unwrap().size(),
+        Type::User(UserType::Struct(t)) => StructCodec::new_dynamic(
+            reflect_fields(shape, t.fields, options, shared),
+            shape.layout.sized_layout().unwrap().size(),
+        ),
+        Type::User(UserType::Enum(t)) => {
+            let layout = shape.layout.sized_layout().unwrap();
+            let tag_size = match t.enum_repr {
+                EnumRepr::U8 | EnumRepr::I8 => 1,
+                EnumRepr::U16 | EnumRepr::I16 => 2,
+                EnumRepr::U32 | EnumRepr::I32 => 4,
+                EnumRepr::U64 | EnumRepr::I64 => 8,
+                EnumRepr::USize | EnumRepr::ISize => core::mem::size_of::<usize>(),
+                EnumRepr::RustNPO => todo!("{shape:?}"),
+            };
+            let variants = t.variants.iter().map(|variant| {
+                let discriminant = variant.discriminant.expect("discriminant") as u64;
+                let fields = (!variant.data.fields.is_empty()).then(|| {
+                    StructCodec::new_dynamic(
+                        reflect_fields(shape, variant.data.fields, options, shared),
+                        layout.size(),
                     )
-                }),
-                shape.layout.sized_layout().unwrap().size(),
-            )
+                });
+                EnumVariant::new(discriminant & mask(tag_size as u32 * 8), fields)
+            });
+            Box::new(EnumCodec::new(variants.collect(), tag_size, layout))
         }
         Type::User(UserType::Opaque) => {
             match shape.def {
@@ -71,20 +217,44 @@ pub fn reflect(shape: &Shape) -> DynamicCodec {
                     Box::new(BoxedSliceCodec::<VecMarker>::new(
                         t.layout.sized_layout().unwrap(),
                         reflect(t),
+                        (t.vtable.sized().unwrap().drop_in_place)(),
                     ))
                 }
                 Def::Pointer(PointerDef {
-                    known: Some(KnownPointer::Box),
+                    known: Some(known @ (KnownPointer::Box | KnownPointer::Arc | KnownPointer::Rc)),
                     pointee: Some(pointee),
+                    vtable,
                     ..
                 }) => {
-                    match pointee().ty {
+                    let pointee = pointee();
+                    match pointee.ty {
                         Type::Sequence(SequenceType::Slice(SliceType { t })) => {
                             let _ = t;
                             // TODO Facet isn't implemented on Box<[T]> yet.
                             todo!("Box<[T]> is ready to be implemented {shape:?}");
                         }
-                        _ => todo!("{shape:?}"),
+                        _ => {
+                            let clone_into = attributes.shared.then(|| {
+                                assert!(
+                                    known != KnownPointer::Box,
+                                    "#[facet(bitcode(shared))] requires Arc<T> or Rc<T>, not {shape}"
+                                );
+                                if cfg!(not(feature = "std")) {
+                                    panic!("#[facet(bitcode(shared))] requires the std feature");
+                                }
+                                shared.set(true);
+                                (shape.vtable.sized().unwrap().clone_into)().unwrap()
+                            });
+                            Box::new(PointerCodec::new(
+                                reflect(pointee),
+                                pointee.layout.sized_layout().unwrap(),
+                                shape.layout.sized_layout().unwrap().size(),
+                                vtable.borrow_fn.unwrap(),
+                                vtable.new_into_fn.unwrap(),
+                                clone_into,
+                                shape.id.get(),
+                            ))
+                        }
                     }
                 }
                 _ => todo!("{shape:?}"),
@@ -95,11 +265,24 @@ pub fn reflect(shape: &Shape) -> DynamicCodec {
             wide: true,
             target,
         })) => match target().ty {
-            // TODO unsound for testing, shouldn't be able to decode &[T], only Box<[T]>.
             Type::Sequence(SequenceType::Slice(SliceType { t })) => {
-                Box::new(BoxedSliceCodec::<BoxedSliceMarker>::new(
-                    t.layout.sized_layout().unwrap(),
-                    reflect(t),
+                let layout = t.layout.sized_layout().unwrap();
+                match borrowed_elements(t, options) {
+                    Some(elements) => Box::new(BoxedSliceCodec::<InputMarker<false>>::new(
+                        layout, elements, None,
+                    )),
+                    None => Box::new(BoxedSliceCodec::<ArenaMarker>::new(
+                        layout,
+                        reflect(t),
+                        None,
+                    )),
+                }
+            }
+            Type::Primitive(PrimitiveType::Textual(TextualType::Str)) => {
+                Box::new(BoxedSliceCodec::<InputMarker<true>>::new(
+                    Layout::new::<u8>(),
+                    primitive::<u8>(),
+                    None,
                 ))
             }
             _ => todo!("{shape:?}"),
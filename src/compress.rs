@@ -0,0 +1,197 @@
+use crate::bits::{BitReader, BitWriter};
+use crate::consume::consume_byte_arrays;
+use crate::error::{err, Result};
+use crate::output::Output;
+use alloc::collections::BinaryHeap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use facet_core::Facet;
+
+const RAW: u8 = 0;
+const HUFFMAN: u8 = 1;
+/// Mode byte and uncompressed length.
+const HEADER_SIZE: usize = 1 + 8;
+/// Code lengths fit in 4 bits each.
+const MAX_CODE_LEN: u8 = 15;
+const CODE_LENGTHS_SIZE: usize = 256 / 2;
+
+/// Serializes a `T:` [`Facet`] into a [`Vec<u8>`] and compresses it with a Huffman code.
+pub fn serialize_compressed<'facet, T: Facet<'facet> + ?Sized>(t: &T) -> Vec<u8> {
+    compress(&crate::serialize(t))
+}
+
+/// Decompresses a [`&[u8]`][`prim@slice`] written by [`serialize_compressed`] and deserializes it
+/// into an instance of `T:` [`Facet`]. `T` can't borrow from the decompressed bytes, which are
+/// freed before returning.
+pub fn deserialize_compressed<T: for<'facet> Facet<'facet>>(bytes: &[u8]) -> Result<T> {
+    crate::deserialize(&decompress(bytes)?)
+}
+
+/// Writes the header followed by either `bytes` or their code lengths and Huffman codes.
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + bytes.len());
+    out.push(RAW);
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+
+    let mut frequencies = [0u64; 256];
+    for &b in bytes {
+        frequencies[b as usize] += 1;
+    }
+    let lengths = code_lengths(frequencies);
+    let bits: u64 = (0..256).map(|i| frequencies[i] * lengths[i] as u64).sum();
+    if CODE_LENGTHS_SIZE as u64 + bits.div_ceil(8) >= bytes.len() as u64 {
+        out.extend_from_slice(bytes);
+        return out;
+    }
+
+    out[0] = HUFFMAN;
+    out.extend(lengths.chunks_exact(2).map(|l| l[0] | (l[1] << 4)));
+    // Generated lengths are always a valid code.
+    let codes = canonical_codes(&lengths).unwrap();
+    let mut output = Output::new(&mut out);
+    let mut writer = BitWriter::new(&mut output);
+    for &b in bytes {
+        writer.write(codes[b as usize] as u64, lengths[b as usize] as u32);
+    }
+    writer.finish();
+    out
+}
+
+fn decompress(mut input: &[u8]) -> Result<Vec<u8>> {
+    let header = consume_byte_arrays(&mut input, HEADER_SIZE, 1)?;
+    let len = u64::from_le_bytes(header[1..].try_into().unwrap());
+    match header[0] {
+        RAW if len == input.len() as u64 => Ok(input.to_vec()),
+        HUFFMAN => {
+            let packed = consume_byte_arrays(&mut input, CODE_LENGTHS_SIZE, 1)?;
+            let mut lengths = [0u8; 256];
+            for (i, &p) in packed.iter().enumerate() {
+                lengths[i * 2] = p & 0xF;
+                lengths[i * 2 + 1] = p >> 4;
+            }
+            // Every byte takes at least 1 bit, so a crafted length can't allocate more than 8
+            // times the input.
+            if len > input.len() as u64 * 8 {
+                return err("invalid uncompressed length");
+            }
+            let codes = canonical_codes(&lengths)?;
+            let max_len = lengths.iter().copied().max().unwrap_or(0) as u32;
+            // Maps the next `max_len` bits to `symbol << 4 | length` or 0 for invalid codes.
+            let mut table = vec![0u16; 1 << max_len];
+            for symbol in 0..256 {
+                let length = lengths[symbol] as usize;
+                if length != 0 {
+                    for entry in table[codes[symbol] as usize..]
+                        .iter_mut()
+                        .step_by(1 << length)
+                    {
+                        *entry = (symbol as u16) << 4 | length as u16;
+                    }
+                }
+            }
+
+            let mut reader = BitReader::new(input);
+            let mut out = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                let entry = table[reader.peek(max_len) as usize];
+                if entry == 0 {
+                    return err("invalid Huffman code");
+                }
+                reader.skip(entry as u32 & 0xF)?;
+                out.push((entry >> 4) as u8);
+            }
+            if reader.bytes_read() != input.len() {
+                return err("Expected EOF");
+            }
+            Ok(out)
+        }
+        RAW => err("invalid uncompressed length"),
+        _ => err("invalid compression mode"),
+    }
+}
+
+/// Huffman code lengths limited to [`MAX_CODE_LEN`] bits, 0 for bytes that don't occur.
+fn code_lengths(mut frequencies: [u64; 256]) -> [u8; 256] {
+    loop {
+        let lengths = huffman_code_lengths(&frequencies);
+        if lengths.iter().all(|&l| l <= MAX_CODE_LEN) {
+            return lengths;
+        }
+        // Flatten the distribution until the code is short enough.
+        for f in &mut frequencies {
+            if *f != 0 {
+                *f = (*f >> 1) | 1;
+            }
+        }
+    }
+}
+
+fn huffman_code_lengths(frequencies: &[u64; 256]) -> [u8; 256] {
+    let symbols: Vec<usize> = (0..256).filter(|&i| frequencies[i] != 0).collect();
+    let mut lengths = [0u8; 256];
+    if let [symbol] = symbols.as_slice() {
+        lengths[*symbol] = 1;
+        return lengths;
+    }
+
+    // Nodes are created after their children, so parents always have higher indices.
+    let mut parents = vec![usize::MAX; symbols.len()];
+    let mut heap: BinaryHeap<_> = symbols
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| Reverse((frequencies[s], i)))
+        .collect();
+    while let (Some(Reverse((a, i))), Some(Reverse((b, j)))) = (heap.pop(), heap.pop()) {
+        let parent = parents.len();
+        parents.push(usize::MAX);
+        parents[i] = parent;
+        parents[j] = parent;
+        heap.push(Reverse((a + b, parent)));
+    }
+    let mut depths = vec![0u8; parents.len()];
+    for i in (0..parents.len()).rev() {
+        if let Some(&depth) = depths.get(parents[i]) {
+            depths[i] = depth.saturating_add(1);
+        }
+    }
+    for (i, &s) in symbols.iter().enumerate() {
+        lengths[s] = depths[i];
+    }
+    lengths
+}
+
+/// Assigns canonical codes, bit reversed since [`BitWriter`] writes the least significant bit
+/// first. Returns an error if the lengths are over-subscribed.
+fn canonical_codes(lengths: &[u8; 256]) -> Result<[u16; 256]> {
+    let kraft: u32 = lengths
+        .iter()
+        .filter(|&&l| l != 0)
+        .map(|&l| 1 << (MAX_CODE_LEN - l))
+        .sum();
+    if kraft > 1 << MAX_CODE_LEN {
+        return err("invalid Huffman code lengths");
+    }
+
+    let mut counts = [0u16; MAX_CODE_LEN as usize + 1];
+    for &l in lengths {
+        counts[l as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut next = [0u16; MAX_CODE_LEN as usize + 1];
+    let mut code = 0u16;
+    for length in 1..next.len() {
+        code = (code + counts[length - 1]) << 1;
+        next[length] = code;
+    }
+
+    let mut codes = [0u16; 256];
+    for (symbol, &length) in lengths.iter().enumerate() {
+        if length != 0 {
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            codes[symbol] = code.reverse_bits() >> (16 - length);
+        }
+    }
+    Ok(codes)
+}
@@ -1,4 +1,4 @@
-use crate::error::{err, Result};
+use crate::error::{err, err_eof, Result};
 
 pub fn consume_byte_arrays<'a>(
     input: &mut &'a [u8],
@@ -7,7 +7,7 @@ pub fn consume_byte_arrays<'a>(
 ) -> Result<&'a [u8]> {
     // Uses division to avoid the posibility of array_length * num_arrays overflowing.
     if input.len() / array_length < num_arrays {
-        return err("EOF");
+        return err_eof(input, num_arrays.saturating_mul(array_length));
     }
     // Safety: Checked that num_arrays * array_length bytes exists above.
     unsafe {
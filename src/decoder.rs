@@ -12,6 +12,12 @@ pub trait Decoder: Send + Sync {
     /// 2. so we don't have to implement dropping a partially initalized output
     fn validate(&self, input: &mut &[u8], length: usize) -> Result<()>;
 
+    /// Returns the size of each encoded value if [`Self::validate`] can only fail by running out
+    /// of bytes (every bit pattern is valid), in which case it's just a length check.
+    fn infallible_size(&self) -> Option<usize> {
+        None
+    }
+
     /// Required to have the exact same results (but possibly faster) as
     /// `unsafe { decoder.decode_many(input, std::ptr::slice_from_raw_parts_mut(erased, 1)) };`
     /// Safety: ^^^
@@ -21,6 +27,32 @@ pub trait Decoder: Send + Sync {
     unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]);
 
     unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize);
+
+    /// Whether [`Self::decode_many_strided_into`] can decode into existing values.
+    fn can_decode_into(&self) -> bool {
+        false
+    }
+
+    /// Like [`Self::decode_many_strided`], but the first `initialized` values are overwritten
+    /// (reusing their allocations) instead of uninitialized. The default is only correct for
+    /// values which don't own memory.
+    /// Safety: ^^^ and [`Self::can_decode_into`] if `initialized` isn't 0.
+    unsafe fn decode_many_strided_into(
+        &self,
+        input: &mut &[u8],
+        erased: *mut [u8],
+        stride: usize,
+        _initialized: usize,
+    ) {
+        self.decode_many_strided(input, erased, stride);
+    }
+
+    /// Consumes `length` values which are encoded as is and returns where they start, so they can
+    /// be borrowed instead of decoded. Only called on the elements of borrowed slices.
+    /// Safety: validate must have succeded with the same parameters.
+    unsafe fn decode_borrowed(&self, _input: &mut &[u8], _length: usize) -> *const u8 {
+        unreachable!();
+    }
 }
 
 #[inline(always)]
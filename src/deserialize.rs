@@ -1,12 +1,33 @@
+use crate::codec::Codec;
 use crate::consume::expect_eof;
 use crate::error::Error;
+use crate::options::EncodeOptions;
 use core::mem::MaybeUninit;
+use core::ops::Deref;
 use facet_core::Facet;
 
-/// Deserializes a [`&[u8]`][`prim@slice`] into an instance of `T:` [`Facet`].
-pub fn deserialize<'facet, T: Facet<'facet>>(bytes: &[u8]) -> Result<T, Error> {
-    let codec = crate::reflect(T::SHAPE);
+/// Deserializes a [`&[u8]`][`prim@slice`] into an instance of `T:` [`Facet`]. `&[u8]` and `&str`
+/// borrow from `bytes`.
+pub fn deserialize<'facet, T: Facet<'facet>>(bytes: &'facet [u8]) -> Result<T, Error> {
+    deserialize_with(bytes, EncodeOptions::default())
+}
+
+/// Like [`deserialize`] for bytes serialized with
+/// [`serialize_with`](crate::serialize_with) and the same [`EncodeOptions`].
+pub fn deserialize_with<'facet, T: Facet<'facet>>(
+    bytes: &'facet [u8],
+    options: EncodeOptions,
+) -> Result<T, Error> {
+    let codec = crate::reflect(T::SHAPE, options);
+    // Safety: reflected from `T`.
+    unsafe { deserialize_with_codec(codec, bytes) }
+}
 
+/// Safety: `codec` must be the codec of `T`.
+pub(crate) unsafe fn deserialize_with_codec<'facet, T: Facet<'facet>>(
+    codec: impl Deref<Target = dyn Codec>,
+    bytes: &'facet [u8],
+) -> Result<T, Error> {
     let mut validated = bytes;
     codec.validate(&mut validated, 1)?;
     expect_eof(validated)?;
@@ -20,18 +41,66 @@ pub fn deserialize<'facet, T: Facet<'facet>>(bytes: &[u8]) -> Result<T, Error> {
     unsafe { Ok(uninit.assume_init()) }
 }
 
+/// Like [`deserialize`], but decodes into `t`, reusing the allocations of its [`Vec`]s (which
+/// grow when needed).
+///
+/// [`Vec`]: alloc::vec::Vec
+pub fn deserialize_into<'facet, T: Facet<'facet>>(
+    bytes: &'facet [u8],
+    t: &mut T,
+) -> Result<(), Error> {
+    deserialize_into_with(bytes, t, EncodeOptions::default())
+}
+
+/// Like [`deserialize_into`] for bytes serialized with
+/// [`serialize_with`](crate::serialize_with) and the same [`EncodeOptions`].
+pub fn deserialize_into_with<'facet, T: Facet<'facet>>(
+    bytes: &'facet [u8],
+    t: &mut T,
+    options: EncodeOptions,
+) -> Result<(), Error> {
+    let codec = crate::reflect(T::SHAPE, options);
+    // Safety: reflected from `T`.
+    unsafe { deserialize_into_with_codec(codec, bytes, t) }
+}
+
+/// Safety: `codec` must be the codec of `T`.
+pub(crate) unsafe fn deserialize_into_with_codec<'facet, T: Facet<'facet>>(
+    codec: impl Deref<Target = dyn Codec>,
+    bytes: &'facet [u8],
+    t: &mut T,
+) -> Result<(), Error> {
+    if !codec.can_decode_into() {
+        // Contains values which can't be decoded into (e.g. Box<T>), so replace all of `t`.
+        *t = deserialize_with_codec(codec, bytes)?;
+        return Ok(());
+    }
+    let mut validated = bytes;
+    codec.validate(&mut validated, 1)?;
+    expect_eof(validated)?;
+
+    let mut decoded = bytes;
+    let erased = core::ptr::slice_from_raw_parts_mut(t as *mut T as *mut u8, 1);
+    unsafe { codec.decode_many_strided_into(&mut decoded, erased, size_of::<T>(), 1) };
+    debug_assert_eq!(validated.len(), decoded.len());
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::benches::Vertex;
+    use crate::benches::{ShuffledVertex, Vertex};
     use alloc::vec;
+    use alloc::vec::Vec;
     use core::fmt::Debug;
     use facet::Facet;
     use test::{black_box, Bencher};
 
-    fn roundtrip<'facet, T: Facet<'facet> + Debug + PartialEq>(t: &T) {
+    fn roundtrip<T: for<'facet> Facet<'facet> + Debug + PartialEq>(t: &T) {
         let bytes = crate::serialize(t);
-        let deserialized = crate::deserialize::<T>(&bytes).expect(core::any::type_name::<T>());
+        assert_eq!(crate::encoded_size(t), bytes.len());
+        let deserialized = crate::deserialize::<T>(&bytes)
+            .unwrap_or_else(|e| panic!("{}: {e}", core::any::type_name::<T>()));
         assert_eq!(t, &deserialized);
     }
 
@@ -70,12 +139,13 @@ mod tests {
         assert!(crate::deserialize::<char>(&crate::serialize(&(0xDFFFu32 + 1))).is_ok());
     }
 
-    // TODO re-add.
-    /*#[test]
-    #[should_panic = "cannot deserialize &[T]"]
+    #[test]
     fn test_invalid_deserialize_slice() {
-        let _ = crate::deserialize::<&[&[u8]]>(&[]);
-    }*/
+        // Slices other than &[u8] need an Arena (see deserialize_in).
+        assert!(crate::deserialize::<&[&[u8]]>(&[]).is_err());
+        let bytes = crate::serialize(&vec![&[1u32][..]]);
+        assert!(crate::deserialize::<Vec<&[u32]>>(&bytes).is_err());
+    }
 
     #[test]
     fn test_struct() {
@@ -90,6 +160,860 @@ mod tests {
         roundtrip(&vec![FakeTransparent(1), FakeTransparent(2)]);
     }
 
+    #[test]
+    fn test_float_xor() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct Reading {
+            #[facet(bitcode(float = xor))]
+            value: f64,
+            #[facet(bitcode(float = auto))]
+            other: f32,
+            id: u32,
+        }
+        let reading = |i: u32| Reading {
+            value: 20.0 + i as f64 * 0.01,
+            other: i as f32,
+            id: i,
+        };
+        roundtrip(&reading(5));
+        roundtrip(&vec![reading(5)]);
+        roundtrip(&(0..100).map(reading).collect::<Vec<_>>());
+        roundtrip(&Vec::<Reading>::new());
+
+        #[derive(Facet)]
+        struct Bits {
+            #[facet(bitcode(float = xor))]
+            f32s: Vec<f32>,
+            #[facet(bitcode(float = auto))]
+            f64s: Vec<f64>,
+        }
+        let f32s = [
+            0.0,
+            -0.0,
+            1.0,
+            f32::NAN,
+            f32::from_bits(0x7FC0_1234),
+            f32::INFINITY,
+        ];
+        let f64s = [
+            0.0,
+            -0.0,
+            1.0,
+            f64::NAN,
+            f64::from_bits(0xFFF8_0000_0000_1234),
+            1.0,
+        ];
+        let bits = Bits {
+            f32s: f32s.to_vec(),
+            f64s: f64s.to_vec(),
+        };
+        let decoded: Bits = deserialize(&crate::serialize(&bits)).unwrap();
+        let to_bits = |v: &[f32]| v.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(to_bits(&decoded.f32s), to_bits(&f32s));
+        let to_bits = |v: &[f64]| v.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(to_bits(&decoded.f64s), to_bits(&f64s));
+    }
+
+    #[test]
+    fn test_invalid_float_xor() {
+        #[derive(Debug, Facet)]
+        struct Xor(#[facet(bitcode(float = xor))] f32);
+        #[derive(Debug, Facet)]
+        struct Auto(#[facet(bitcode(float = auto))] f32);
+
+        // Reuses a window that doesn't exist.
+        assert!(deserialize::<Xor>(&[0b01]).is_err());
+        // Leading zeros + meaningful bits > 32.
+        assert!(deserialize::<Xor>(&[0b11 | 31 << 2, 0b1111 << 2, 0]).is_err());
+        assert!(deserialize::<Auto>(&[2, 0, 0, 0, 0]).is_err());
+        assert!(deserialize::<Auto>(&[]).is_err());
+    }
+
+    #[test]
+    fn test_float_shuffle() {
+        roundtrip(&ShuffledVertex::from(Vertex::new(42)));
+        roundtrip(
+            &(0..100)
+                .map(|i| ShuffledVertex::from(Vertex::new(i)))
+                .collect::<Vec<_>>(),
+        );
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Shuffled(#[facet(bitcode(float = shuffle))] Vec<f64>);
+        roundtrip(&Shuffled(vec![]));
+        roundtrip(&Shuffled(vec![1.0, -2.5, f64::MAX, f64::MIN_POSITIVE]));
+    }
+
+    #[test]
+    fn test_float_quantize() {
+        #[derive(Debug, Facet)]
+        struct Position {
+            #[facet(bitcode(quantize(bits = 16, min = -10.0, max = 10.0)))]
+            x: f32,
+            #[facet(bitcode(quantize(bits = 12, min = 0, max = 1)))]
+            y: f64,
+        }
+        let positions: Vec<_> = (0..100)
+            .map(|i| Position {
+                x: i as f32 * 0.1 - 5.0,
+                y: i as f64 / 100.0,
+            })
+            .collect();
+        let bytes = crate::serialize(&positions);
+        assert_eq!(bytes.len(), 4 + (2 + 200) + (2 + 150));
+
+        let decoded: Vec<Position> = deserialize(&bytes).unwrap();
+        for (p, d) in positions.iter().zip(&decoded) {
+            assert!((p.x - d.x).abs() <= 10.0 / 65535.0, "{p:?} {d:?}");
+            assert!((p.y - d.y).abs() <= 0.5 / 4095.0, "{p:?} {d:?}");
+        }
+
+        // Out of range values are clamped and NaN becomes min.
+        let clamped = Position {
+            x: 100.0,
+            y: f64::NAN,
+        };
+        let bytes_one = crate::serialize(&clamped);
+        assert_eq!(bytes_one.len(), (2 + 2) + (2 + 2));
+        let decoded: Position = deserialize(&bytes_one).unwrap();
+        assert_eq!((decoded.x, decoded.y), (10.0, 0.0));
+
+        #[derive(Debug, Facet)]
+        struct Mismatched {
+            #[facet(bitcode(quantize(bits = 16, min = -10.0, max = 20.0)))]
+            x: f32,
+            #[facet(bitcode(quantize(bits = 12, min = 0, max = 1)))]
+            y: f64,
+        }
+        assert!(deserialize::<Vec<Mismatched>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_enum() {
+        #[derive(Debug, PartialEq, Facet)]
+        #[repr(u8)]
+        enum Direction {
+            North,
+            East,
+            South,
+            West,
+        }
+        roundtrip(&Direction::South);
+        roundtrip(&vec![Direction::North, Direction::West, Direction::East]);
+
+        #[derive(Debug, PartialEq, Facet)]
+        #[repr(u16)]
+        #[allow(dead_code)]
+        enum Shape {
+            Empty = 7,
+            Circle(f32),
+            Rect { w: u32, h: u32 },
+            Path(Vec<(i16, i16)>),
+        }
+        roundtrip(&Shape::Rect { w: 3, h: 4 });
+        let shapes = vec![
+            Shape::Circle(1.5),
+            Shape::Empty,
+            Shape::Path(vec![(1, 2), (3, 4)]),
+            Shape::Rect { w: 3, h: 4 },
+            Shape::Circle(2.5),
+            Shape::Path(vec![]),
+        ];
+        roundtrip(&shapes);
+        roundtrip(&vec![Shape::Circle(1.0), Shape::Circle(2.0)]);
+
+        // Enums inside structs are encoded with a stride.
+        roundtrip(&vec![(1u8, Shape::Circle(1.0)), (2u8, Shape::Empty)]);
+        roundtrip(&vec![(1u8, Shape::Circle(1.0)), (2u8, Shape::Circle(3.0))]);
+    }
+
+    #[test]
+    fn test_invalid_enum() {
+        #[derive(Debug, PartialEq, Facet)]
+        #[repr(u8)]
+        #[allow(dead_code)]
+        enum Three {
+            A,
+            B,
+            C,
+        }
+        assert!(deserialize::<Three>(&[2]).is_ok());
+        assert!(deserialize::<Three>(&[3]).is_err());
+        // 4 packed 2 bit variants: C, A, B, C.
+        assert!(deserialize::<Vec<Three>>(&[4, 0, 0, 0, 0b10_01_00_10]).is_ok());
+        assert!(deserialize::<Vec<Three>>(&[4, 0, 0, 0, 0b11_01_00_10]).is_err());
+
+        #[derive(Debug, PartialEq, Facet)]
+        #[repr(u8)]
+        enum One {
+            A,
+        }
+        #[derive(Debug, PartialEq, Facet)]
+        #[repr(u8)]
+        enum OneWithFields {
+            A(u16),
+        }
+        roundtrip(&vec![One::A, One::A]);
+        roundtrip(&vec![OneWithFields::A(1), OneWithFields::A(2)]);
+        roundtrip(&vec![
+            (1u8, OneWithFields::A(3)),
+            (2u8, OneWithFields::A(4)),
+        ]);
+        // One variant is still written with 1 bit, so lengths must be backed by the input.
+        assert!(deserialize::<Vec<One>>(&[2, 0, 0, 0, 0b00]).is_ok());
+        assert!(deserialize::<Vec<One>>(&[2, 0, 0, 0, 0b10]).is_err());
+        assert!(deserialize::<Vec<One>>(&(1u32 << 30).to_le_bytes()).is_err());
+        #[cfg(feature = "std")]
+        {
+            let mut chunk = 0xFFFFFFF0u32.to_le_bytes().to_vec();
+            chunk.extend_from_slice(&0u64.to_le_bytes());
+            let mut reader = crate::ChunkReader::<One, _>::new(chunk.as_slice());
+            let error = reader.next().unwrap().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_shared_lengths() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct Points {
+            x: Vec<f32>,
+            id: u16,
+            y: Vec<f32>,
+            z: Vec<u8>,
+        }
+        let points = |n: usize, m: usize| Points {
+            x: (0..n).map(|i| i as f32).collect(),
+            id: n as u16,
+            y: (0..n).map(|i| i as f32 * 2.0).collect(),
+            z: (0..m).map(|i| i as u8).collect(),
+        };
+        roundtrip(&points(3, 3));
+        roundtrip(&points(3, 2));
+        roundtrip(&vec![points(1, 1), points(0, 0), points(5, 5)]);
+        roundtrip(&vec![points(1, 1), points(0, 2), points(5, 5)]);
+        roundtrip(&Vec::<Points>::new());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_pointer() {
+        use alloc::boxed::Box;
+        use alloc::rc::Rc;
+        use alloc::sync::Arc;
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Material {
+            name: Vec<u8>,
+            roughness: f32,
+        }
+        #[derive(Debug, PartialEq, Facet)]
+        struct Mesh {
+            #[facet(bitcode(shared))]
+            material: Arc<Material>,
+            inline: Arc<Material>,
+            boxed: Box<u16>,
+        }
+        let materials: Vec<_> = (0..3)
+            .map(|i| {
+                Arc::new(Material {
+                    name: vec![i; i as usize],
+                    roughness: i as f32,
+                })
+            })
+            .collect();
+        let meshes: Vec<_> = (0..100)
+            .map(|i| Mesh {
+                material: materials[i % 3].clone(),
+                inline: materials[i % 3].clone(),
+                boxed: Box::new(i as u16),
+            })
+            .collect();
+        roundtrip(&meshes);
+        roundtrip(&meshes[0]);
+
+        let deserialized: Vec<Mesh> = deserialize(&crate::serialize(&meshes)).unwrap();
+        for (i, mesh) in deserialized.iter().enumerate() {
+            let first = &deserialized[i % 3];
+            assert!(Arc::ptr_eq(&mesh.material, &first.material));
+            assert_eq!(Arc::ptr_eq(&mesh.inline, &first.inline), i < 3);
+        }
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Node(#[facet(bitcode(shared))] Vec<Rc<u32>>);
+        let shared = Rc::new(5);
+        let node = Node(vec![shared.clone(), Rc::new(6), shared]);
+        roundtrip(&node);
+        let deserialized: Node = deserialize(&crate::serialize(&node)).unwrap();
+        assert!(Rc::ptr_eq(&deserialized.0[0], &deserialized.0[2]));
+        assert_eq!(Rc::strong_count(&deserialized.0[0]), 2);
+
+        // Sharing is preserved between fields and nesting levels.
+        #[derive(Debug, PartialEq, Facet)]
+        struct Pair(
+            #[facet(bitcode(shared))] Rc<u32>,
+            #[facet(bitcode(shared))] Rc<u32>,
+        );
+        let shared = Rc::new(7);
+        let pair = Pair(shared.clone(), shared);
+        roundtrip(&pair);
+        let bytes = crate::serialize(&pair);
+        assert_eq!(bytes.len(), 4 + 4 + 4);
+        let deserialized: Pair = deserialize(&bytes).unwrap();
+        assert!(Rc::ptr_eq(&deserialized.0, &deserialized.1));
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Group {
+            #[facet(bitcode(shared))]
+            first: Arc<Material>,
+            #[facet(bitcode(shared))]
+            all: Arc<Vec<Arc<Material>>>,
+        }
+        let group = Group {
+            first: materials[0].clone(),
+            all: Arc::new(materials.clone()),
+        };
+        roundtrip(&group);
+        let deserialized: Group = deserialize(&crate::serialize(&group)).unwrap();
+        assert!(Arc::ptr_eq(&deserialized.first, &deserialized.all[0]));
+        assert!(!Arc::ptr_eq(&deserialized.first, &deserialized.all[1]));
+        assert_eq!(Arc::strong_count(&deserialized.first), 2);
+
+        // Sharing is preserved between the elements of a message, but not between messages.
+        let empty = Group {
+            first: materials[0].clone(),
+            all: Arc::new(vec![]),
+        };
+        let groups = vec![group, empty];
+        let deserialized: Vec<Group> = deserialize(&crate::serialize(&groups)).unwrap();
+        assert!(Arc::ptr_eq(&deserialized[0].first, &deserialized[1].first));
+        let other: Group = deserialize(&crate::serialize(&groups[1])).unwrap();
+        assert!(!Arc::ptr_eq(&deserialized[0].first, &other.first));
+        assert_eq!(
+            crate::encoded_size(&groups),
+            crate::serialize(&groups).len()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_invalid_pointer() {
+        use alloc::sync::Arc;
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Shared(#[facet(bitcode(shared))] Vec<Arc<u8>>);
+        // Indices 0, 0, 1 then 2 distinct values.
+        assert!(
+            deserialize::<Shared>(&[3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 5, 6]).is_ok()
+        );
+        // Index 2 refers to a value that hasn't been written yet.
+        assert!(
+            deserialize::<Shared>(&[3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 5, 6]).is_err()
+        );
+        assert!(deserialize::<Shared>(&[1, 0, 0, 0, 1, 0, 0, 0, 5]).is_err());
+
+        // The second column can refer back to the first's allocations.
+        #[derive(Debug, PartialEq, Facet)]
+        struct Pair(
+            #[facet(bitcode(shared))] Arc<u8>,
+            #[facet(bitcode(shared))] Arc<u8>,
+        );
+        assert!(deserialize::<Pair>(&[0, 0, 0, 0, 5, 0, 0, 0, 0]).is_ok());
+        assert!(deserialize::<Pair>(&[0, 0, 0, 0, 5, 1, 0, 0, 0, 6]).is_ok());
+        assert!(deserialize::<Pair>(&[0, 0, 0, 0, 5, 2, 0, 0, 0, 6]).is_err());
+    }
+
+    #[test]
+    fn test_sparse() {
+        #[derive(Debug, Default, PartialEq, Facet)]
+        struct Limits {
+            min: u16,
+            max: u16,
+        }
+        #[derive(Debug, PartialEq, Facet)]
+        struct Entity {
+            id: u32,
+            #[facet(bitcode(sparse))]
+            health: u8,
+            #[facet(bitcode(sparse))]
+            speed: f32,
+            #[facet(bitcode(sparse))]
+            limits: Limits,
+        }
+        let entity = |i: u32| Entity {
+            id: i,
+            health: if i.is_multiple_of(20) { 50 } else { 0 },
+            speed: if i.is_multiple_of(3) { i as f32 } else { 0.0 },
+            limits: if i.is_multiple_of(7) {
+                Limits { min: 1, max: 2 }
+            } else {
+                Limits::default()
+            },
+        };
+        roundtrip(&entity(0));
+        roundtrip(&entity(1));
+        roundtrip(&(0..100).map(entity).collect::<Vec<_>>());
+        roundtrip(&Vec::<Entity>::new());
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Sparse(#[facet(bitcode(sparse))] u8);
+        assert!(deserialize::<Vec<Sparse>>(&[2, 0, 0, 0, 1, 0b10, 5]).is_ok());
+        assert!(deserialize::<Vec<Sparse>>(&[2, 0, 0, 0, 1, 0b11, 5]).is_err());
+        assert!(deserialize::<Vec<Sparse>>(&[2, 0, 0, 0, 2, 0b10, 5]).is_err());
+
+        // Values equal to the default by PartialEq but encoded differently aren't elided.
+        #[derive(Debug, PartialEq, Facet)]
+        struct Speed(#[facet(bitcode(sparse))] f32);
+        let speeds: Vec<_> = (0..100)
+            .map(|i| Speed(if i == 50 { -0.0 } else { 0.0 }))
+            .collect();
+        let decoded: Vec<Speed> = deserialize(&crate::serialize(&speeds)).unwrap();
+        let signs: Vec<_> = decoded.iter().map(|s| s.0.is_sign_negative()).collect();
+        assert_eq!(signs, (0..100).map(|i| i == 50).collect::<Vec<_>>());
+        #[derive(Debug, Default, PartialEq, Facet)]
+        struct Velocity {
+            x: f32,
+        }
+        #[derive(Debug, PartialEq, Facet)]
+        struct Moving(#[facet(bitcode(sparse))] Velocity);
+        let moving: Vec<_> = (0..100)
+            .map(|i| {
+                Moving(Velocity {
+                    x: if i == 50 { -0.0 } else { 0.0 },
+                })
+            })
+            .collect();
+        let decoded: Vec<Moving> = deserialize(&crate::serialize(&moving)).unwrap();
+        let signs: Vec<_> = decoded.iter().map(|m| m.0.x.is_sign_negative()).collect();
+        assert_eq!(signs, (0..100).map(|i| i == 50).collect::<Vec<_>>());
+
+        // Types without floats are compared by PartialEq.
+        #[derive(Debug, PartialEq, Facet)]
+        struct Named(#[facet(bitcode(sparse))] Vec<u8>);
+        let names: Vec<_> = (0..100)
+            .map(|i| Named(if i == 50 { vec![b'a'] } else { vec![] }))
+            .collect();
+        let bytes = crate::serialize(&names);
+        assert!(bytes.len() < 4 + 1 + 100, "defaults aren't elided");
+        assert_eq!(deserialize::<Vec<Named>>(&bytes).unwrap(), names);
+    }
+
+    #[test]
+    #[cfg(feature = "compress")]
+    fn test_compressed() {
+        use crate::{deserialize_compressed, serialize_compressed};
+
+        fn roundtrip_compressed<T: for<'facet> Facet<'facet> + Debug + PartialEq>(t: &T) -> usize {
+            let bytes = serialize_compressed(t);
+            assert_eq!(&deserialize_compressed::<T>(&bytes).unwrap(), t);
+            bytes.len()
+        }
+        roundtrip_compressed(&5u8);
+        roundtrip_compressed(&Vec::<u8>::new());
+        roundtrip_compressed(&vec![7u8; 1000]);
+        let mesh: Vec<_> = (0..1000).map(Vertex::new).collect();
+        let compressed = roundtrip_compressed(&mesh);
+        assert!(compressed < crate::serialize(&mesh).len());
+        // Fibonacci frequencies need code lengths to be limited.
+        let (mut a, mut b) = (1usize, 1usize);
+        let mut fibonacci = vec![];
+        for i in 0..24u8 {
+            fibonacci.extend(core::iter::repeat_n(i, a));
+            (a, b) = (b, a + b);
+        }
+        roundtrip_compressed(&fibonacci);
+
+        // Raw lengths must match and Huffman lengths are bounded by the input.
+        let bytes = serialize_compressed(&vec![7u8; 1000]);
+        assert_eq!(bytes[0], 1);
+        for len in [1001u64, u64::MAX] {
+            let mut crafted = bytes.clone();
+            crafted[1..9].copy_from_slice(&len.to_le_bytes());
+            assert!(deserialize_compressed::<Vec<u8>>(&crafted).is_err());
+        }
+        assert!(deserialize_compressed::<Vec<u8>>(&bytes[..bytes.len() - 1]).is_err());
+        let raw = serialize_compressed(&5u8);
+        assert_eq!(raw, [0, 1, 0, 0, 0, 0, 0, 0, 0, 5]);
+        assert!(deserialize_compressed::<u8>(&[0, 2, 0, 0, 0, 0, 0, 0, 0, 5]).is_err());
+        assert!(deserialize_compressed::<u8>(&[2, 1, 0, 0, 0, 0, 0, 0, 0, 5]).is_err());
+    }
+
+    #[test]
+    fn test_optimize_size() {
+        use crate::{EncodeOptions, Optimize};
+
+        fn roundtrip_size<T: for<'facet> Facet<'facet> + Debug + PartialEq>(t: &T) -> usize {
+            let options = EncodeOptions {
+                optimize: Optimize::Size,
+                ..Default::default()
+            };
+            let bytes = crate::serialize_with(t, options);
+            assert_eq!(crate::encoded_size_with(t, options), bytes.len());
+            assert_eq!(&deserialize_with::<T>(&bytes, options).unwrap(), t);
+            bytes.len()
+        }
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Row {
+            id: u64,
+            offset: i32,
+            kind: u16,
+            flag: i8,
+        }
+        let mut rng = 0x1234_5678_9ABC_DEF0u64;
+        let rows: Vec<_> = (0..1000)
+            .map(|i| {
+                rng = rng.wrapping_mul(6364136223846793005).wrapping_add(1);
+                Row {
+                    id: 1_000_000 + i,
+                    offset: (rng >> 40) as i32 % 100 - 50,
+                    kind: [7, 4000, 65535][(rng >> 60) as usize % 3],
+                    flag: if i < 500 { -1 } else { i8::MIN },
+                }
+            })
+            .collect();
+        let size = roundtrip_size(&rows);
+        assert!(size < crate::serialize(&rows).len() / 4, "{size}");
+
+        roundtrip_size(&rows[0]);
+        roundtrip_size(&Vec::<Row>::new());
+        roundtrip_size(&vec![u64::MAX, 0, u64::MAX]);
+        roundtrip_size(&vec![i64::MIN, i64::MAX, -1, 0, 1]);
+        roundtrip_size(
+            &(0..1000u32)
+                .map(|i| i.wrapping_mul(2654435761))
+                .collect::<Vec<_>>(),
+        );
+        roundtrip_size(&vec![vec![1u8, 2, 3], vec![], vec![4]]);
+    }
+
+    #[test]
+    fn test_invalid_optimize_size() {
+        use crate::{EncodeOptions, Optimize};
+        let options = EncodeOptions {
+            optimize: Optimize::Size,
+            ..Default::default()
+        };
+        let decode = |bytes: &[u8]| deserialize_with::<Vec<u8>>(bytes, options);
+        let header = |tag: u8| [3, 0, 0, 0, tag];
+        let packed = |min: u8, bits: u8| [min, 0, 0, 0, 0, 0, 0, 0, bits];
+
+        assert!(decode(&[header(1).as_slice(), &packed(5, 0)].concat()).is_ok());
+        assert!(decode(&[header(1).as_slice(), &packed(5, 65)].concat()).is_err());
+        assert!(decode(&[header(5).as_slice(), &packed(5, 0)].concat()).is_err());
+
+        // RLE run lengths must add up to the number of values.
+        let rle = |count: u8, length: u8| {
+            let count = [count, 0, 0, 0, 0, 0, 0, 0];
+            [
+                header(3).as_slice(),
+                &count,
+                &packed(5, 0),
+                &packed(length, 0),
+            ]
+            .concat()
+        };
+        assert!(decode(&rle(1, 2)).is_ok());
+        assert!(decode(&rle(1, 1)).is_err());
+        assert!(decode(&rle(1, 3)).is_err());
+        assert!(decode(&rle(4, 0)).is_err());
+
+        // Dictionary indices must be in bounds.
+        let dictionary = |index: u8| {
+            let count = [2, 0, 0, 0, 0, 0, 0, 0];
+            [
+                header(4).as_slice(),
+                &count,
+                &packed(5, 1),
+                &[0b10],
+                &packed(index, 0),
+            ]
+            .concat()
+        };
+        assert!(decode(&dictionary(1)).is_ok());
+        assert!(decode(&dictionary(2)).is_err());
+
+        // Values packed to 0 bits still need a bit of input each, so a long length can't allocate
+        // more than the input.
+        let decode = |bytes: &[u8]| deserialize_with::<Vec<u64>>(bytes, options);
+        let header = |length: u32| [length.to_le_bytes().as_slice(), &[1]].concat();
+        assert!(decode(&[header(1 << 26).as_slice(), &packed(5, 0)].concat()).is_err());
+        assert!(decode(&[header(u32::MAX).as_slice(), &packed(5, 0)].concat()).is_err());
+        let padded = [header(88).as_slice(), &packed(5, 0), &[0]].concat();
+        assert_eq!(decode(&padded).unwrap(), [5; 88]);
+        assert_eq!(crate::serialize_with(&vec![5u64; 88], options), padded);
+        let mut nonzero = padded.clone();
+        *nonzero.last_mut().unwrap() = 1;
+        assert!(decode(&nonzero).is_err());
+        assert!(decode(&padded[..padded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_tiled() {
+        #[derive(Debug, Default, PartialEq, Facet)]
+        struct Wide(u8, u8, u8, u8, u8, u8, u8, bool, i8, u16, Vec<u8>);
+        // Large enough to be tiled, ending in a partial tile.
+        let wide: Vec<_> = (0..50_000u32)
+            .map(|i| {
+                let b = |j: u32| (i * 7 + j) as u8;
+                let tail = vec![b(0); i as usize % 3];
+                Wide(
+                    b(0),
+                    b(1),
+                    b(2),
+                    b(3),
+                    b(4),
+                    b(5),
+                    b(6),
+                    i % 3 == 0,
+                    b(8) as i8,
+                    i as u16,
+                    tail,
+                )
+            })
+            .collect();
+        roundtrip(&wide);
+        let mut bytes = crate::serialize(&wide);
+        let mut into: Vec<_> = (0..10).map(|_| Wide::default()).collect();
+        deserialize_into(&bytes, &mut into).unwrap();
+        assert_eq!(into, wide);
+
+        // The bool column (after the length and 7 u8 columns) is still validated.
+        bytes[4 + 7 * wide.len() + 12_345] = 2;
+        assert!(deserialize::<Vec<Wide>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_struct_plan() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct Mixed {
+            a: u32,
+            b: bool,
+            c: char,
+            d: Vec<u8>,
+            e: u16,
+        }
+        let mixed = Mixed {
+            a: 1,
+            b: true,
+            c: 'c',
+            d: vec![2, 3],
+            e: 4,
+        };
+        roundtrip(&mixed);
+        roundtrip(&vec![mixed]);
+
+        let mut bytes = crate::serialize(&Mixed {
+            a: 1,
+            b: false,
+            c: 'c',
+            d: vec![],
+            e: 4,
+        });
+        assert!(deserialize::<Mixed>(&bytes).is_ok());
+        // Fields are written in order: a at 0, b at 4 and c at 5.
+        bytes[4] = 2;
+        assert!(deserialize::<Mixed>(&bytes).is_err());
+        bytes[4] = 0;
+        bytes[5..9].copy_from_slice(&0xD800u32.to_le_bytes());
+        assert!(deserialize::<Mixed>(&bytes).is_err());
+        assert!(deserialize::<Mixed>(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_into() {
+        use alloc::boxed::Box;
+        use alloc::sync::Arc;
+
+        #[derive(Debug, Default, PartialEq, Facet)]
+        struct Entity {
+            id: u32,
+            position: (f32, f32),
+            inventory: Vec<u16>,
+            cooldowns: Vec<u16>,
+            tags: Vec<Vec<u8>>,
+        }
+        #[derive(Debug, Default, PartialEq, Facet)]
+        struct WorldSnapshot {
+            tick: u32,
+            entities: Vec<Entity>,
+        }
+        let snapshot = |tick: u32, n: u32| WorldSnapshot {
+            tick,
+            entities: (0..n)
+                .map(|i| Entity {
+                    id: i,
+                    position: (i as f32, tick as f32),
+                    inventory: (0..(i + tick) as u16 % 5).collect(),
+                    cooldowns: (0..(i + tick) as u16 % 5).rev().collect(),
+                    tags: (0..i % 3)
+                        .map(|j| vec![j as u8; (i + tick) as usize])
+                        .collect(),
+                })
+                .collect(),
+        };
+
+        let mut world = WorldSnapshot::default();
+        for (tick, n) in [(1, 10), (2, 10), (3, 4), (4, 20), (5, 0), (6, 7)] {
+            let expected = snapshot(tick, n);
+            let bytes = crate::serialize(&expected);
+            let (entities, capacity) = (world.entities.as_ptr(), world.entities.capacity());
+            deserialize_into(&bytes, &mut world).unwrap();
+            assert_eq!(world, expected);
+            if n <= capacity as u32 {
+                assert_eq!(world.entities.as_ptr(), entities);
+            }
+            assert!(deserialize_into(&bytes[..bytes.len() - 1], &mut world).is_err());
+            assert_eq!(world, expected);
+        }
+
+        // Elements which can't be decoded into are dropped.
+        let arc = Arc::new(5u8);
+        let mut arcs = vec![arc.clone(), arc.clone()];
+        deserialize_into(&crate::serialize(&vec![Arc::new(6u8)]), &mut arcs).unwrap();
+        assert_eq!(arcs, [Arc::new(6)]);
+        assert_eq!(Arc::strong_count(&arc), 1);
+
+        // As are values containing them.
+        let mut boxed = (Box::new(1u32), vec![2u32]);
+        deserialize_into(&crate::serialize(&(Box::new(3u32), vec![4u32])), &mut boxed).unwrap();
+        assert_eq!(boxed, (Box::new(3), vec![4]));
+
+        let codec = crate::TypedCodec::<Vec<Vec<u8>>>::new();
+        let mut nested = vec![vec![1u8; 100]];
+        let inner = nested[0].as_ptr();
+        codec
+            .decode_into(&codec.encode(&vec![vec![2], vec![3]]), &mut nested)
+            .unwrap();
+        assert_eq!(nested, [vec![2], vec![3]]);
+        assert_eq!(nested[0].as_ptr(), inner);
+
+        // Borrowed slices are replaced, not freed.
+        #[derive(Debug, Default, PartialEq, Facet)]
+        struct Named<'a> {
+            id: u32,
+            name: &'a str,
+            bytes: &'a [u8],
+        }
+        let bytes = crate::serialize(&"ab");
+        let mut s = "xyz";
+        deserialize_into(&bytes, &mut s).unwrap();
+        assert_eq!(s, "ab");
+        let expected = vec![
+            Named {
+                id: 1,
+                name: "abc",
+                bytes: &[1, 2],
+            },
+            Named {
+                id: 2,
+                name: "",
+                bytes: &[3],
+            },
+        ];
+        let bytes = crate::serialize(&expected);
+        let mut named = vec![Named {
+            id: 0,
+            name: "old",
+            bytes: &[0; 3],
+        }];
+        deserialize_into(&bytes, &mut named).unwrap();
+        assert_eq!(named, expected);
+    }
+
+    #[test]
+    fn test_infallible() {
+        let floats: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        roundtrip(&floats);
+        let bytes = crate::serialize(&floats);
+        assert!(deserialize::<Vec<f32>>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mesh: Vec<_> = (0..100).map(Vertex::new).collect();
+        roundtrip(&mesh);
+        let bytes = crate::serialize(&mesh);
+        assert!(deserialize::<Vec<Vertex>>(&bytes[..bytes.len() - 1]).is_err());
+
+        // Not infallible because of the bool.
+        #[derive(Debug, PartialEq, Facet)]
+        struct Fallible(u32, bool);
+        let fallible: Vec<_> = (0..100).map(|i| Fallible(i, i % 2 == 0)).collect();
+        roundtrip(&fallible);
+        let mut bytes = crate::serialize(&fallible);
+        *bytes.last_mut().unwrap() = 2;
+        assert!(deserialize::<Vec<Fallible>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        #[derive(Debug, PartialEq, Facet)]
+        struct Empty {}
+        roundtrip(&Empty {});
+        assert_eq!(crate::serialize(&Empty {}).len(), 0);
+
+        let vertex = Vertex::new(5);
+        roundtrip(&vertex);
+        let bytes = crate::serialize(&vertex);
+        assert_eq!(bytes.len(), 3 * 4 + 3);
+        assert!(deserialize::<Vertex>(&bytes[..bytes.len() - 1]).is_err());
+        assert!(deserialize::<Vertex>(&[bytes.as_slice(), &[0]].concat()).is_err());
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Checked(u8, bool, u16, char);
+        roundtrip(&Checked(1, true, 2, 'x'));
+        let bytes = crate::serialize(&Checked(1, true, 2, 'x'));
+        let mut invalid = bytes.clone();
+        invalid[1] = 2;
+        assert!(deserialize::<Checked>(&invalid).is_err());
+        let mut invalid = bytes.clone();
+        invalid[4..8].copy_from_slice(&0x110000u32.to_le_bytes());
+        assert!(deserialize::<Checked>(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_borrowed() {
+        fn roundtrip_borrowed<'a, T: Facet<'a> + Debug + PartialEq>(t: &T, bytes: &'a [u8]) -> T {
+            let decoded = deserialize::<T>(bytes).unwrap();
+            assert_eq!(&decoded, t);
+            decoded
+        }
+        let bytes = crate::serialize(&"héllo");
+        let s: &str = roundtrip_borrowed(&"héllo", &bytes);
+        assert!(bytes.as_ptr_range().contains(&s.as_ptr()));
+        let bytes = crate::serialize(&b"abc".as_slice());
+        let b: &[u8] = roundtrip_borrowed(&b"abc".as_slice(), &bytes);
+        assert!(bytes.as_ptr_range().contains(&b.as_ptr()));
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            data: &'a [u8],
+            tags: Vec<&'a str>,
+        }
+        let borrowed = Borrowed {
+            name: "x",
+            data: b"yz",
+            tags: vec!["", "a", "bc"],
+        };
+        let bytes = crate::serialize(&borrowed);
+        roundtrip_borrowed(&borrowed, &bytes);
+        let options = EncodeOptions {
+            optimize: crate::Optimize::Size,
+            ..Default::default()
+        };
+        let bytes = crate::serialize_with(&borrowed, options);
+        assert_eq!(
+            deserialize_with::<Borrowed>(&bytes, options).unwrap(),
+            borrowed
+        );
+        let bytes = crate::serialize(&vec![borrowed.tags.clone(); 2]);
+        roundtrip_borrowed(&vec![borrowed.tags.clone(); 2], &bytes);
+
+        let mut invalid = crate::serialize(&"é");
+        *invalid.last_mut().unwrap() = 0xFF;
+        assert!(deserialize::<&str>(&invalid).is_err());
+        assert!(deserialize::<Vec<&str>>(&crate::serialize(&vec!["a"])[..4]).is_err());
+    }
+
     #[bench]
     fn bench_decode_u32_facet_bitcode(b: &mut Bencher) {
         let original = 5u32;
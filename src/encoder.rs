@@ -1,16 +1,23 @@
 use crate::codec::Codec;
+use crate::output::Output;
+use crate::plan::Instruction;
+use crate::slice::SliceCodec;
 use crate::struct_::StructCodec;
-use alloc::vec::Vec;
 use core::alloc::Layout;
 
 pub trait Encoder: Send + Sync {
     /// Required have the exact same results (but possibly faster) as
     /// `unsafe { codec.encode_many(std::ptr::slice_from_raw_parts(erased, 1), out) };``
-    unsafe fn encode_one(&self, erased: *const u8, out: &mut Vec<u8>);
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output);
 
-    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Vec<u8>);
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output);
 
-    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Vec<u8>);
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output);
+
+    /// Returns the length `out` would have after encoding `values` if it had `out_len` bytes
+    /// (padding depends on it). Codecs whose size depends on the contents of the values (rather
+    /// than their lengths) measure them if `exact` and otherwise return a lower bound.
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize;
 
     // TODO used by try_decode_in_place, move to Codec?
     fn in_place(&self) -> bool {
@@ -20,10 +27,62 @@ pub trait Encoder: Send + Sync {
     fn as_struct_codec_mut(&mut self) -> Option<&mut StructCodec> {
         None
     }
+
+    fn as_slice_codec(&self) -> Option<&dyn SliceCodec> {
+        None
+    }
+
+    /// Returns an instruction which has the same results as encoding, validating or decoding one
+    /// value at `offset`, if any.
+    fn instruction(&self, _offset: usize) -> Option<Instruction> {
+        None
+    }
+}
+
+/// Values passed to [`Encoder::encoded_size`], which are measured where they are instead of being
+/// gathered into a column like for encoding. Each run is a pointer to a number of values `stride`
+/// bytes apart, and `offset` is added to each value (to select a field).
+#[derive(Copy, Clone)]
+pub struct Values<'a> {
+    runs: &'a [(*const u8, usize)],
+    stride: usize,
+    offset: usize,
+    len: usize,
+}
+
+impl<'a> Values<'a> {
+    pub fn new(runs: &'a [(*const u8, usize)], stride: usize) -> Self {
+        Self {
+            runs,
+            stride,
+            offset: 0,
+            len: runs.iter().map(|&(_, count)| count).sum(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the field at `offset` of each value.
+    pub fn field(self, offset: usize) -> Self {
+        Self {
+            offset: self.offset + offset,
+            ..self
+        }
+    }
+
+    /// Returns a pointer to each value.
+    pub fn iter(self) -> impl Iterator<Item = *const u8> + Clone + 'a {
+        let Self { stride, offset, .. } = self;
+        self.runs.iter().flat_map(move |&(start, count)| {
+            (0..count).map(move |i| start.wrapping_byte_add(offset + i * stride))
+        })
+    }
 }
 
 #[inline(always)]
-pub unsafe fn encode_one_or_many(codec: &dyn Codec, erased: *const [u8], out: &mut Vec<u8>) {
+pub unsafe fn encode_one_or_many(codec: &dyn Codec, erased: *const [u8], out: &mut Output) {
     if erased.len() == 1 {
         codec.encode_one(erased as *const u8, out);
     } else {
@@ -38,24 +97,15 @@ pub unsafe fn try_encode_in_place(
     layout: Layout,
     n_elements: usize,
     encode: &mut dyn FnMut(*mut u8),
-    out: &mut Vec<u8>,
+    out: &mut Output,
 ) {
-    let dst_size = layout.size() * n_elements;
-    let (dst, allocation) = if codec.in_place() {
-        out.reserve(dst_size);
-        (out.as_mut_ptr_range().end, None)
-    } else {
-        let (allocation, stride) = layout.repeat(n_elements).unwrap();
-        debug_assert_eq!(stride, layout.size()); // TODO when can this fail?
-        (alloc::alloc::alloc(allocation), Some(allocation)) // TODO scratch allocator like rkyv?
-    };
-
-    encode(dst);
-
-    if let Some(allocation) = allocation {
-        codec.encode_many(core::ptr::slice_from_raw_parts(dst, n_elements), out);
-        alloc::alloc::dealloc(dst, allocation);
-    } else {
-        out.set_len(out.len() + dst_size);
+    if codec.in_place() {
+        return out.extend_with(layout.size() * n_elements, encode);
     }
+    let (allocation, stride) = layout.repeat(n_elements).unwrap();
+    debug_assert_eq!(stride, layout.size()); // TODO when can this fail?
+    let dst = alloc::alloc::alloc(allocation); // TODO scratch allocator like rkyv?
+    encode(dst);
+    codec.encode_many(core::ptr::slice_from_raw_parts(dst, n_elements), out);
+    alloc::alloc::dealloc(dst, allocation);
 }
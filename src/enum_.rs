@@ -0,0 +1,278 @@
+use crate::bits::{BitReader, BitWriter};
+use crate::codec::DynamicCodec;
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, Values};
+use crate::error::{err, error, Result};
+use crate::output::Output;
+use crate::scratch::Scratch;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+
+pub struct EnumVariant {
+    discriminant: u64,
+    /// `None` if the variant has no fields.
+    fields: Option<DynamicCodec>,
+}
+
+impl EnumVariant {
+    pub fn new(discriminant: u64, fields: Option<DynamicCodec>) -> Self {
+        Self {
+            discriminant,
+            fields,
+        }
+    }
+}
+
+/// Writes a column of variant indices packed to ⌈log2(variants.len())⌉ bits each (at least 1),
+/// followed by the fields of each variant (in variant order) for the elements with that variant.
+pub struct EnumCodec {
+    variants: Vec<EnumVariant>,
+    /// Size of the discriminant at offset 0.
+    tag_size: usize,
+    layout: Layout,
+    bits: u32,
+}
+
+impl EnumCodec {
+    pub fn new(variants: Vec<EnumVariant>, tag_size: usize, layout: Layout) -> Self {
+        // At least 1 bit (which must be 0 with one variant), so validating a column bounds the
+        // number of enums allocated for it by the input's size.
+        let bits = match variants.len() {
+            0 | 1 => 1,
+            n => usize::BITS - (n - 1).leading_zeros(),
+        };
+        Self {
+            variants,
+            tag_size,
+            layout,
+            bits,
+        }
+    }
+
+    /// Safety: `erased` must be a valid enum.
+    #[inline(always)]
+    unsafe fn read_variant(&self, erased: *const u8) -> usize {
+        let mut tag = 0u64;
+        // TODO swap_bytes on big endian.
+        core::ptr::copy_nonoverlapping(erased, &mut tag as *mut u64 as *mut u8, self.tag_size);
+        let variant = self.variants.iter().position(|v| v.discriminant == tag);
+        // Safety: a valid enum has one of the discriminants.
+        variant.unwrap_unchecked()
+    }
+
+    #[inline(always)]
+    unsafe fn write_tag(&self, variant: usize, erased: *mut u8) {
+        let tag = self.variants.get_unchecked(variant).discriminant;
+        // TODO swap_bytes on big endian.
+        core::ptr::copy_nonoverlapping(&tag as *const u64 as *const u8, erased, self.tag_size);
+    }
+
+    /// Safety: `erased` must be valid enums at `stride`.
+    unsafe fn variants(&self, erased: *const [u8], stride: usize) -> Vec<u32> {
+        let src = erased as *const u8;
+        (0..erased.len())
+            .map(|i| self.read_variant(src.byte_add(i * stride)) as u32)
+            .collect()
+    }
+
+    /// Returns the number of enums with each variant.
+    /// Safety: `variants` must be valid variant indices.
+    unsafe fn counts(&self, variants: &[u32]) -> Vec<usize> {
+        let mut counts = vec![0; self.variants.len()];
+        for &variant in variants {
+            *counts.get_unchecked_mut(variant as usize) += 1;
+        }
+        counts
+    }
+
+    /// Returns the fields of the variant of all `n` enums if they can be coded in place.
+    fn only_fields(&self, counts: &[usize], n: usize, stride: usize) -> Option<&DynamicCodec> {
+        let variant = counts.iter().position(|&count| count == n && n != 0)?;
+        let fields = self.variants[variant].fields.as_ref()?;
+        (stride == self.layout.size()).then_some(fields)
+    }
+
+    /// Returns a column for the enums of each variant which has fields and any enums.
+    fn columns(&self, counts: &[usize]) -> Vec<Option<Scratch>> {
+        self.variants
+            .iter()
+            .zip(counts)
+            .map(|(variant, &count)| {
+                (variant.fields.is_some() && count != 0).then(|| Scratch::new(self.layout, count))
+            })
+            .collect()
+    }
+
+    /// Calls `f` with the fields of each variant (in variant order) and a column of the enums with
+    /// that variant.
+    /// Safety: `variants` must be [`Self::variants`] of `erased`.
+    unsafe fn for_each_fields(
+        &self,
+        erased: *const [u8],
+        stride: usize,
+        variants: &[u32],
+        mut f: impl FnMut(&DynamicCodec, *const [u8]),
+    ) {
+        let size = self.layout.size();
+        let counts = self.counts(variants);
+        if let Some(fields) = self.only_fields(&counts, erased.len(), stride) {
+            f(fields, erased);
+            return;
+        }
+
+        // Gather the enums of each variant in one pass so their fields can be encoded in columns.
+        let columns = self.columns(&counts);
+        let mut dsts: Vec<_> = columns
+            .iter()
+            .map(|column| column.as_ref().map(Scratch::as_ptr))
+            .collect();
+        let src = erased as *const u8;
+        for (j, &variant) in variants.iter().enumerate() {
+            if let Some(dst) = dsts.get_unchecked_mut(variant as usize) {
+                core::ptr::copy_nonoverlapping(src.byte_add(j * stride), *dst, size);
+                *dst = dst.byte_add(size);
+            }
+        }
+        for ((variant, column), count) in self.variants.iter().zip(&columns).zip(counts) {
+            if let (Some(fields), Some(column)) = (&variant.fields, column) {
+                f(
+                    fields,
+                    core::ptr::slice_from_raw_parts(column.as_ptr(), count),
+                );
+            }
+        }
+    }
+}
+
+impl Encoder for EnumCodec {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        let variant = self.read_variant(erased);
+        let mut writer = BitWriter::new(out);
+        writer.write(variant as u64, self.bits);
+        writer.finish();
+        if let Some(fields) = &self.variants.get_unchecked(variant).fields {
+            fields.encode_one(erased, out);
+        }
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        self.encode_many_strided(erased, self.layout.size(), out);
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        let variants = self.variants(erased, stride);
+        let mut writer = BitWriter::new(out);
+        for &variant in &variants {
+            writer.write(variant as u64, self.bits);
+        }
+        writer.finish();
+
+        self.for_each_fields(erased, stride, &variants, |fields, column| {
+            fields.encode_many(column, out);
+        });
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        // The enums of each variant, which are measured where they are instead of gathered.
+        let mut runs = vec![Vec::new(); self.variants.len()];
+        for erased in values.iter() {
+            runs.get_unchecked_mut(self.read_variant(erased))
+                .push((erased, 1));
+        }
+        let mut out_len = out_len + (values.len() * self.bits as usize).div_ceil(8);
+        for (variant, runs) in self.variants.iter().zip(&runs) {
+            if let (Some(fields), false) = (&variant.fields, runs.is_empty()) {
+                let values = Values::new(runs, self.layout.size());
+                out_len = fields.encoded_size(values, out_len, exact);
+            }
+        }
+        out_len
+    }
+}
+
+impl Decoder for EnumCodec {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        let total_bits = length
+            .checked_mul(self.bits as usize)
+            .ok_or_else(|| error("length overflow"))?;
+        let bytes = consume_byte_arrays(input, total_bits.div_ceil(8), 1)?;
+
+        let mut counts = vec![0usize; self.variants.len()];
+        let mut reader = BitReader::new(bytes);
+        for _ in 0..length {
+            // Safety: consumed enough bytes for `length` variants above.
+            let variant = unsafe { reader.read_unchecked(self.bits) } as usize;
+            let Some(count) = counts.get_mut(variant) else {
+                return err("invalid enum variant");
+            };
+            *count += 1;
+        }
+
+        for (variant, count) in self.variants.iter().zip(counts) {
+            if let (Some(fields), 1..) = (&variant.fields, count) {
+                fields.validate(input, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn can_decode_into(&self) -> bool {
+        // Variants with fields could own memory, which the new variant would leak.
+        self.variants.iter().all(|variant| variant.fields.is_none())
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        let bytes = consume_byte_arrays_unchecked(input, (self.bits as usize).div_ceil(8), 1);
+        let variant = BitReader::new(bytes).read_unchecked(self.bits) as usize;
+        if let Some(fields) = &self.variants.get_unchecked(variant).fields {
+            fields.decode_one(input, erased);
+        }
+        self.write_tag(variant, erased);
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        self.decode_many_strided(input, erased, self.layout.size());
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        let n = erased.len();
+        let bytes = consume_byte_arrays_unchecked(input, (n * self.bits as usize).div_ceil(8), 1);
+        let mut reader = BitReader::new(bytes);
+        let variants: Vec<u32> = (0..n)
+            .map(|_| reader.read_unchecked(self.bits) as u32)
+            .collect();
+
+        let size = self.layout.size();
+        let dst = erased as *mut u8;
+        let counts = self.counts(&variants);
+        if let Some(fields) = self.only_fields(&counts, n, stride) {
+            fields.decode_many(input, erased);
+        } else {
+            // Decode the fields of each variant into a column, then scatter them to the enums
+            // with that variant in one pass (tags are written after).
+            let columns = self.columns(&counts);
+            for ((variant, column), &count) in self.variants.iter().zip(&columns).zip(&counts) {
+                if let (Some(fields), Some(column)) = (&variant.fields, column) {
+                    let column = core::ptr::slice_from_raw_parts_mut(column.as_ptr(), count);
+                    fields.decode_many(input, column);
+                }
+            }
+            let mut srcs: Vec<_> = columns
+                .iter()
+                .map(|column| column.as_ref().map(|c| c.as_ptr() as *const u8))
+                .collect();
+            for (j, &variant) in variants.iter().enumerate() {
+                if let Some(src) = srcs.get_unchecked_mut(variant as usize) {
+                    core::ptr::copy_nonoverlapping(*src, dst.byte_add(j * stride), size);
+                    *src = src.byte_add(size);
+                }
+            }
+        }
+
+        for (j, &variant) in variants.iter().enumerate() {
+            self.write_tag(variant as usize, dst.byte_add(j * stride));
+        }
+    }
+}
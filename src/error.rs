@@ -7,6 +7,28 @@ pub fn err<T>(msg: &'static str) -> Result<T> {
     Err(error(msg))
 }
 
+/// Short version of `Err(error("EOF"))` for `input` ending before `len` bytes. With `std` it also
+/// records where the input needed to end, see [`take_needed_end`].
+pub fn err_eof<T>(_input: &[u8], _len: usize) -> Result<T> {
+    #[cfg(feature = "std")]
+    NEEDED_END.set((_input.as_ptr() as usize).saturating_add(_len));
+    err("EOF")
+}
+
+// Kept outside of Error so it stays zero-sized in release mode.
+#[cfg(feature = "std")]
+std::thread_local! {
+    static NEEDED_END: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+/// Returns the address the input needed to extend to if the last error on this thread was the
+/// input ending too soon, which [`deserialize_from_reader`](crate::deserialize_from_reader) reads
+/// more input for. Call before validating to clear it.
+#[cfg(feature = "std")]
+pub(crate) fn take_needed_end() -> Option<usize> {
+    Some(NEEDED_END.take()).filter(|&end| end != 0)
+}
+
 /// Creates an error with a message that might be displayed.
 pub fn error(_msg: &'static str) -> Error {
     #[cfg(debug_assertions)]
@@ -27,6 +49,8 @@ type ErrorImpl = ();
 /// In release mode, the error is a zero-sized type for efficiency.
 #[cfg_attr(test, derive(PartialEq))]
 pub struct Error(ErrorImpl);
+#[cfg(not(debug_assertions))]
+const _: () = assert!(core::mem::size_of::<Error>() == 0);
 impl Debug for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         #[cfg(debug_assertions)]
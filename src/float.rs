@@ -0,0 +1,395 @@
+use crate::bits::{mask, BitReader, BitWriter};
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, Values};
+use crate::error::{err, error, Result};
+use crate::output::Output;
+use crate::primitive::{Primitive, PrimitiveCodec};
+use crate::strided;
+
+/// How a `f32`/`f64` column is encoded, selected with `#[facet(bitcode(float = ...))]`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FloatEncoding {
+    /// Gorilla-style XOR with the previous value. Lossless (including NaN payloads) and very
+    /// small when consecutive values are similar.
+    Xor,
+    /// Raw or [`Self::Xor`], whichever is smaller, recorded in a 1 byte tag per column.
+    Auto,
+    /// Same size as raw, but split into byte planes (all the byte 0s, then all the byte 1s...)
+    /// so that general-purpose compressors can find the repetitive exponent bytes.
+    Shuffle,
+    /// Lossy, each value is clamped to `[min, max]` and rounded to one of `2^bits` evenly
+    /// spaced values. A 2 byte hash of the parameters is written in each column so that a reader
+    /// with different parameters fails validation.
+    Quantize { bits: u8, min: f64, max: f64 },
+}
+
+impl FloatEncoding {
+    /// Panics if the parameters are invalid.
+    pub fn quantize(bits: u8, min: f64, max: f64) -> Self {
+        assert!((1..=32).contains(&bits), "quantize bits must be in 1..=32");
+        assert!(
+            min.is_finite() && max.is_finite() && min < max,
+            "quantize range must be finite and min < max"
+        );
+        Self::Quantize { bits, min, max }
+    }
+}
+
+const TAG_RAW: u8 = 0;
+const TAG_XOR: u8 = 1;
+
+pub trait Float: Primitive {
+    const BITS: u32;
+    fn to_bits_u64(self) -> u64;
+    fn from_bits_u64(bits: u64) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_f64(v: f64) -> Self;
+}
+
+impl Float for f32 {
+    const BITS: u32 = 32;
+
+    fn to_bits_u64(self) -> u64 {
+        self.to_bits() as u64
+    }
+
+    fn from_bits_u64(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+}
+
+impl Float for f64 {
+    const BITS: u32 = 64;
+
+    fn to_bits_u64(self) -> u64 {
+        self.to_bits()
+    }
+
+    fn from_bits_u64(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+}
+
+pub struct FloatCodec<T> {
+    encoding: FloatEncoding,
+    raw: PrimitiveCodec<T>,
+}
+
+impl<T: Default> FloatCodec<T> {
+    pub fn new(encoding: FloatEncoding) -> Self {
+        Self {
+            encoding,
+            raw: Default::default(),
+        }
+    }
+}
+
+/// Each value is XORed with the previous one (starting with 0) and written as:
+/// - `0` if the XOR is 0.
+/// - `10` followed by the meaningful bits if they fit in the previous window.
+/// - `11` followed by the number of leading zeros, the number of meaningful bits minus 1 and the
+///   meaningful bits, which become the new window.
+///
+/// Calls `write` with the bits to write and their number.
+#[inline(always)]
+fn xor_bits<T: Float>(values: impl Iterator<Item = u64>, mut write: impl FnMut(u64, u32)) {
+    let header_bits = T::BITS.trailing_zeros();
+    let mut prev = 0u64;
+    let (mut window_lead, mut window_trail) = (u32::MAX, u32::MAX);
+    for value in values {
+        let xor = value ^ prev;
+        prev = value;
+        if xor == 0 {
+            write(0, 1);
+            continue;
+        }
+        let lead = xor.leading_zeros() - (64 - T::BITS);
+        let trail = xor.trailing_zeros();
+        if lead >= window_lead && trail >= window_trail {
+            write(0b01, 2);
+            write(xor >> window_trail, T::BITS - window_lead - window_trail);
+        } else {
+            let len = T::BITS - lead - trail;
+            write(0b11, 2);
+            write(lead as u64, header_bits);
+            write((len - 1) as u64, header_bits);
+            write(xor >> trail, len);
+            (window_lead, window_trail) = (lead, trail);
+        }
+    }
+}
+
+/// Writes [`xor_bits`].
+fn xor_encode<T: Float>(values: impl Iterator<Item = u64>, out: &mut Output) {
+    let mut writer = BitWriter::new(out);
+    xor_bits::<T>(values, |bits, n| writer.write(bits, n));
+    writer.finish();
+}
+
+/// Returns the size of [`xor_encode`]'s output without writing it.
+fn xor_size<T: Float>(values: impl Iterator<Item = u64>) -> usize {
+    let mut total_bits = 0;
+    xor_bits::<T>(values, |_, n| total_bits += n as usize);
+    total_bits.div_ceil(8)
+}
+
+/// Calls `f` with each of the `length` values, returning an error if `input` is malformed.
+#[inline(always)]
+fn xor_decode<T: Float>(input: &mut &[u8], length: usize, mut f: impl FnMut(u64)) -> Result<()> {
+    let header_bits = T::BITS.trailing_zeros();
+    let mut reader = BitReader::new(input);
+    let mut prev = 0u64;
+    let mut window = None;
+    for _ in 0..length {
+        if reader.read(1)? != 0 {
+            let (lead, len) = if reader.read(1)? == 0 {
+                window.ok_or_else(|| error("missing xor window"))?
+            } else {
+                let lead = reader.read(header_bits)? as u32;
+                let len = reader.read(header_bits)? as u32 + 1;
+                if lead + len > T::BITS {
+                    return err("invalid xor window");
+                }
+                window = Some((lead, len));
+                (lead, len)
+            };
+            prev ^= reader.read(len)? << (T::BITS - lead - len);
+        }
+        f(prev);
+    }
+    *input = &input[reader.bytes_read()..];
+    Ok(())
+}
+
+impl<T: Float> FloatCodec<T> {
+    /// Safety: `pointers` must be valid to read `T`s.
+    unsafe fn bits(
+        pointers: impl Iterator<Item = *const u8> + Clone,
+    ) -> impl Iterator<Item = u64> + Clone {
+        pointers
+            .map(|p| unsafe { core::ptr::read_unaligned(p as *const T) })
+            .map(T::to_bits_u64)
+    }
+
+    /// Whether [`FloatEncoding::Auto`] picks XOR (if it's smaller than raw) for `n` values.
+    fn auto_is_xor(values: impl Iterator<Item = u64>, n: usize) -> bool {
+        xor_size::<T>(values) < n * core::mem::size_of::<T>()
+    }
+
+    /// Returns true if the column is XOR encoded, consuming the tag if there is one.
+    /// Safety: the input must have been validated.
+    unsafe fn decode_is_xor(&self, input: &mut &[u8]) -> bool {
+        match self.encoding {
+            FloatEncoding::Xor => true,
+            FloatEncoding::Auto => {
+                let (tag, remaining) = input.split_first().unwrap_unchecked();
+                *input = remaining;
+                *tag == TAG_XOR
+            }
+            FloatEncoding::Shuffle | FloatEncoding::Quantize { .. } => unreachable!(),
+        }
+    }
+}
+
+unsafe fn shuffle_encode<T>(erased: *const [u8], stride: usize, out: &mut Output) {
+    let n = erased.len();
+    out.extend_with(n * core::mem::size_of::<T>(), |dst| {
+        for plane in 0..core::mem::size_of::<T>() {
+            // TODO swap_bytes on big endian.
+            let src = (erased as *const u8).byte_add(plane);
+            strided::gather(1, src, stride, dst.add(plane * n), n);
+        }
+    });
+}
+
+/// Safety: `input` must contain at least `erased.len()` `T`s.
+unsafe fn shuffle_decode<T>(input: &mut &[u8], erased: *mut [u8], stride: usize) {
+    let n = erased.len();
+    let bytes = consume_byte_arrays_unchecked(input, n, core::mem::size_of::<T>());
+
+    for plane in 0..core::mem::size_of::<T>() {
+        // TODO swap_bytes on big endian.
+        let dst = (erased as *mut u8).byte_add(plane);
+        strided::scatter(1, bytes.as_ptr().add(plane * n), dst, stride, n);
+    }
+}
+
+/// Size of the hash of `bits`, `min`, `max` which starts a quantized column.
+const QUANTIZE_HEADER_SIZE: usize = 2;
+
+/// 16 bit FNV-1a of the parameters, so a reader with different ones (almost always) fails
+/// validation without writing them in full.
+fn quantize_header(bits: u8, min: f64, max: f64) -> [u8; QUANTIZE_HEADER_SIZE] {
+    let mut hash = 0x811c9dc5u32;
+    let bytes = [bits]
+        .into_iter()
+        .chain(min.to_le_bytes())
+        .chain(max.to_le_bytes());
+    for byte in bytes {
+        hash = (hash ^ byte as u32).wrapping_mul(0x01000193);
+    }
+    ((hash >> 16) as u16 ^ hash as u16).to_le_bytes()
+}
+
+fn quantize_encode(
+    values: impl Iterator<Item = f64>,
+    bits: u8,
+    min: f64,
+    max: f64,
+    out: &mut Output,
+) {
+    out.extend_from_slice(&quantize_header(bits, min, max));
+    let steps = mask(bits as u32) as f64;
+    let scale = steps / (max - min);
+    let mut writer = BitWriter::new(out);
+    for v in values {
+        // NaN becomes min. Adding 0.5 then truncating rounds (f64::round isn't in core).
+        let q = ((v - min) * scale + 0.5).clamp(0.0, steps);
+        writer.write(if q.is_nan() { 0 } else { q as u64 }, bits as u32);
+    }
+    writer.finish();
+}
+
+impl<T: Float> Encoder for FloatCodec<T> {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        self.encode_many(core::ptr::slice_from_raw_parts(erased, 1), out);
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        self.encode_many_strided(erased, core::mem::size_of::<T>(), out);
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        let src = erased as *const u8;
+        let values = Self::bits((0..erased.len()).map(|i| src.byte_add(i * stride)));
+
+        match self.encoding {
+            FloatEncoding::Xor => xor_encode::<T>(values, out),
+            FloatEncoding::Shuffle => shuffle_encode::<T>(erased, stride, out),
+            FloatEncoding::Quantize { bits, min, max } => {
+                let values = (0..erased.len()).map(|i| unsafe {
+                    core::ptr::read_unaligned(src.byte_add(i * stride) as *const T).to_f64()
+                });
+                quantize_encode(values, bits, min, max, out)
+            }
+            FloatEncoding::Auto => {
+                if Self::auto_is_xor(values.clone(), erased.len()) {
+                    out.push(TAG_XOR);
+                    xor_encode::<T>(values, out);
+                } else {
+                    out.push(TAG_RAW);
+                    self.raw.encode_many_strided(erased, stride, out);
+                }
+            }
+        }
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        let n = values.len();
+        let raw_size = n * core::mem::size_of::<T>();
+        let size = match self.encoding {
+            FloatEncoding::Shuffle => raw_size,
+            FloatEncoding::Quantize { bits, .. } => {
+                QUANTIZE_HEADER_SIZE + (n * bits as usize).div_ceil(8)
+            }
+            // At least 1 bit per value.
+            FloatEncoding::Xor if !exact => n.div_ceil(8),
+            FloatEncoding::Xor => xor_size::<T>(Self::bits(values.iter())),
+            FloatEncoding::Auto if !exact => 1 + n.div_ceil(8),
+            FloatEncoding::Auto => 1 + xor_size::<T>(Self::bits(values.iter())).min(raw_size),
+        };
+        out_len + size
+    }
+}
+
+impl<T: Float> Decoder for FloatCodec<T> {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        if self.encoding == FloatEncoding::Shuffle {
+            return self.raw.validate(input, length);
+        }
+        if let FloatEncoding::Quantize { bits, min, max } = self.encoding {
+            let header = consume_byte_arrays(input, 1, QUANTIZE_HEADER_SIZE)?;
+            if header != quantize_header(bits, min, max) {
+                return err("mismatched quantize parameters");
+            }
+            let total_bits = length
+                .checked_mul(bits as usize)
+                .ok_or_else(|| error("length overflow"))?;
+            consume_byte_arrays(input, total_bits.div_ceil(8), 1)?;
+            return Ok(());
+        }
+        if self.encoding == FloatEncoding::Auto {
+            match consume_byte_arrays(input, 1, 1)?[0] {
+                TAG_RAW => return self.raw.validate(input, length),
+                TAG_XOR => {}
+                _ => return err("invalid float tag"),
+            }
+        }
+        xor_decode::<T>(input, length, |_| {})
+    }
+
+    fn infallible_size(&self) -> Option<usize> {
+        if self.encoding == FloatEncoding::Shuffle {
+            return self.raw.infallible_size();
+        }
+        None
+    }
+
+    fn can_decode_into(&self) -> bool {
+        true
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        self.decode_many(input, core::ptr::slice_from_raw_parts_mut(erased, 1));
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        self.decode_many_strided(input, erased, core::mem::size_of::<T>());
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        if self.encoding == FloatEncoding::Shuffle {
+            return shuffle_decode::<T>(input, erased, stride);
+        }
+        if let FloatEncoding::Quantize { bits, min, max } = self.encoding {
+            let n = erased.len();
+            consume_byte_arrays_unchecked(input, 1, QUANTIZE_HEADER_SIZE);
+            let bytes = consume_byte_arrays_unchecked(input, (n * bits as usize).div_ceil(8), 1);
+            let mut reader = BitReader::new(bytes);
+            let step = (max - min) / mask(bits as u32) as f64;
+            let mut dst = erased as *mut u8;
+            for _ in 0..n {
+                let q = reader.read_unchecked(bits as u32);
+                core::ptr::write_unaligned(dst as *mut T, T::from_f64(min + q as f64 * step));
+                dst = dst.byte_add(stride);
+            }
+            return;
+        }
+        if !self.decode_is_xor(input) {
+            return self.raw.decode_many_strided(input, erased, stride);
+        }
+        let mut dst = erased as *mut u8;
+        let result = xor_decode::<T>(input, erased.len(), |bits| unsafe {
+            core::ptr::write_unaligned(dst as *mut T, T::from_bits_u64(bits));
+            dst = dst.byte_add(stride);
+        });
+        debug_assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,315 @@
+use crate::options::EncodeOptions;
+use alloc::vec::Vec;
+use facet_core::Facet;
+use std::io::{self, Read, Write};
+
+/// Longest varint of a `u64`.
+const MAX_VARINT_SIZE: usize = 10;
+
+/// How the length of each frame is written.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LengthPrefix {
+    /// LEB128: 7 bits per byte, least significant first, 1 byte for frames shorter than 128.
+    #[default]
+    Varint,
+    /// Little endian `u32`.
+    U32,
+}
+
+/// Options for [`FrameWriter`], which must be read by a [`FrameReader`] with the same options.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FrameOptions {
+    pub prefix: LengthPrefix,
+    /// Larger frames can't be written, and aren't read (before allocating for them).
+    pub max_frame_size: usize,
+    pub encode: EncodeOptions,
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        Self {
+            prefix: LengthPrefix::default(),
+            max_frame_size: 64 << 20,
+            encode: EncodeOptions::default(),
+        }
+    }
+}
+
+/// Writes messages to a stream (e.g. a `TcpStream`), each serialized into a frame preceded by its
+/// length. Read them with [`FrameReader`].
+pub struct FrameWriter<W> {
+    writer: W,
+    options: FrameOptions,
+    /// Reused to serialize each message.
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, FrameOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: FrameOptions) -> Self {
+        Self {
+            writer,
+            options,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Writes `t` in a frame. Frames larger than [`FrameOptions::max_frame_size`] are an
+    /// [`io::ErrorKind::InvalidInput`] error, and nothing is written.
+    pub fn write<'facet, T: Facet<'facet> + ?Sized>(&mut self, t: &T) -> io::Result<()> {
+        self.buffer.clear();
+        crate::serialize_into_with(&mut self.buffer, t, self.options.encode);
+        let size = self.buffer.len();
+        if size > self.options.max_frame_size
+            || (self.options.prefix == LengthPrefix::U32 && size > u32::MAX as usize)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame larger than max_frame_size",
+            ));
+        }
+        let mut prefix = [0; MAX_VARINT_SIZE];
+        let prefix_size = match self.options.prefix {
+            LengthPrefix::Varint => write_varint(size as u64, &mut prefix),
+            LengthPrefix::U32 => {
+                prefix[..4].copy_from_slice(&(size as u32).to_le_bytes());
+                4
+            }
+        };
+        // The frame isn't serialized after the prefix, since EncodeOptions::align is relative to
+        // the start of the frame.
+        self.writer.write_all(&prefix[..prefix_size])?;
+        self.writer.write_all(&self.buffer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads the frames written by [`FrameWriter`] from a stream, waiting for partial frames to
+/// arrive. Each frame is read into a buffer which is reused by the next one, so `&[u8]` and `&str`
+/// of a message borrow from the reader.
+///
+/// Decoding errors are returned as [`io::ErrorKind::InvalidData`] wrapping an
+/// [`Error`](crate::Error).
+pub struct FrameReader<R> {
+    reader: R,
+    options: FrameOptions,
+    /// Reused to read each frame.
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, FrameOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: FrameOptions) -> Self {
+        Self {
+            reader,
+            options,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Reads the message in the next frame, or returns `None` if the stream ended between frames.
+    pub fn read<'a, T: Facet<'a>>(&'a mut self) -> io::Result<Option<T>> {
+        let Some(size) = self.read_prefix()? else {
+            return Ok(None);
+        };
+        if size > self.options.max_frame_size as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame larger than max_frame_size",
+            ));
+        }
+        // Grows with the input instead of trusting the size.
+        self.buffer.clear();
+        (&mut self.reader)
+            .take(size)
+            .read_to_end(&mut self.buffer)?;
+        if self.buffer.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        crate::deserialize_with(&self.buffer, self.options.encode)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns `None` if the stream ends before the prefix.
+    fn read_prefix(&mut self) -> io::Result<Option<u64>> {
+        let mut first = 0;
+        loop {
+            match self.reader.read(core::slice::from_mut(&mut first)) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        match self.options.prefix {
+            LengthPrefix::Varint => {
+                let mut size = 0u64;
+                let mut byte = first;
+                for i in 0..MAX_VARINT_SIZE {
+                    if i != 0 {
+                        self.reader.read_exact(core::slice::from_mut(&mut byte))?;
+                    }
+                    let bits = (byte & 0x7F) as u64;
+                    if bits << (7 * i) >> (7 * i) != bits {
+                        break; // Overflow.
+                    }
+                    size |= bits << (7 * i);
+                    if byte & 0x80 == 0 {
+                        return Ok(Some(size));
+                    }
+                }
+                Err(io::Error::new(io::ErrorKind::InvalidData, "invalid varint"))
+            }
+            LengthPrefix::U32 => {
+                let mut bytes = [first, 0, 0, 0];
+                self.reader.read_exact(&mut bytes[1..])?;
+                Ok(Some(u32::from_le_bytes(bytes) as u64))
+            }
+        }
+    }
+}
+
+/// Writes `v` as a varint to the start of `out`, returning its size.
+fn write_varint(mut v: u64, out: &mut [u8; MAX_VARINT_SIZE]) -> usize {
+    let mut size = 0;
+    loop {
+        out[size] = (v & 0x7F) as u8;
+        v >>= 7;
+        size += 1;
+        if v == 0 {
+            return size;
+        }
+        out[size - 1] |= 0x80;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Debug;
+    use facet::Facet;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_truncated_frame_allocation() {
+        // A prefix of the maximum frame size followed by a few bytes only allocates for those.
+        let mut stream = vec![0; MAX_VARINT_SIZE];
+        let prefix_size = write_varint(64 << 20, (&mut stream[..]).try_into().unwrap());
+        stream.truncate(prefix_size);
+        stream.extend_from_slice(&[1, 2, 3]);
+        let mut reader = FrameReader::new(stream.as_slice());
+        let error = reader.read::<u32>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(reader.buffer.capacity() < 1 << 20);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_frames() {
+        use crate::{FrameOptions, FrameReader, FrameWriter, LengthPrefix};
+        use std::io::Write;
+        use std::os::unix::net::UnixStream;
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Message<'a> {
+            id: u32,
+            name: &'a str,
+            payload: Vec<u8>,
+        }
+        let message = |id: u32, n: usize| Message {
+            id,
+            name: "frame",
+            payload: vec![id as u8; n],
+        };
+        // Larger than the socket buffer, so it's written and read in parts.
+        let sizes = [0, 1, 127, 128, 100_000, 3_000_000, 5];
+
+        for prefix in [LengthPrefix::Varint, LengthPrefix::U32] {
+            let options = FrameOptions {
+                prefix,
+                ..Default::default()
+            };
+            let (a, b) = UnixStream::pair().unwrap();
+            let writer = std::thread::spawn(move || {
+                let mut writer = FrameWriter::with_options(a, options);
+                for (i, &n) in sizes.iter().enumerate() {
+                    writer.write(&message(i as u32, n)).unwrap();
+                }
+                writer.write(&7u64).unwrap();
+            });
+
+            let mut reader = FrameReader::with_options(b, options);
+            let mut names = vec![];
+            for (i, &n) in sizes.iter().enumerate() {
+                let m: Message = reader.read().unwrap().unwrap();
+                assert_eq!(m, message(i as u32, n));
+                names.push(m.name.as_ptr());
+            }
+            // The buffer is reused once it's large enough, so smaller frames don't allocate.
+            assert_eq!(names[5], names[6]);
+            assert_eq!(reader.read::<u64>().unwrap(), Some(7));
+            writer.join().unwrap();
+            // The writer was dropped, which closed the stream between frames.
+            assert_eq!(reader.read::<u64>().unwrap(), None);
+        }
+
+        let options = FrameOptions {
+            max_frame_size: 100,
+            ..Default::default()
+        };
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut writer = FrameWriter::with_options(a, options);
+        let error = writer.write(&message(0, 100)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        writer.write(&message(1, 10)).unwrap();
+        // A frame larger than the maximum, an invalid frame, then a partial frame.
+        let a = writer.get_mut();
+        a.write_all(&[0x80, 0x80, 0x80, 0x80, 0x01]).unwrap();
+        a.write_all(&[1, 2]).unwrap();
+        a.write_all(&[4, 0]).unwrap();
+        drop(writer);
+
+        let mut reader = FrameReader::with_options(b, options);
+        assert_eq!(reader.read::<Message>().unwrap(), Some(message(1, 10)));
+        let error = reader.read::<Message>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = reader.read::<bool>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = reader.read::<u32>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
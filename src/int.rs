@@ -0,0 +1,365 @@
+use crate::bits::{mask, BitReader, BitWriter};
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, Values};
+use crate::error::{err, error, Result};
+use crate::output::Output;
+use crate::plan::Instruction;
+use crate::primitive::{Primitive, PrimitiveCodec};
+use alloc::vec::Vec;
+
+const RAW: u8 = 0;
+const PACKED: u8 = 1;
+const DELTA: u8 = 2;
+const RLE: u8 = 3;
+const DICTIONARY: u8 = 4;
+
+/// Minimum and width of a packed column.
+const PACKED_HEADER_SIZE: usize = 8 + 1;
+/// Number of runs or dictionary entries.
+const COUNT_SIZE: usize = 8;
+
+/// Columns are padded to at least one bit per value, so validating a column bounds the number of
+/// values allocated for it by the input's size, even if they're packed to 0 bits.
+fn min_column_size(n: usize) -> usize {
+    n.div_ceil(8)
+}
+
+pub trait Int: Primitive {
+    const SIGNED: bool;
+}
+
+macro_rules! impl_int {
+    ($($t:ty => $signed:literal),+) => {
+        $(impl Int for $t {
+            const SIGNED: bool = $signed;
+        })+
+    };
+}
+impl_int!(u8 => false, u16 => false, u32 => false, u64 => false);
+impl_int!(i8 => true, i16 => true, i32 => true, i64 => true);
+
+/// Integer codec for [`Optimize::Size`](crate::Optimize::Size) which writes a tag followed by
+/// the smallest of:
+/// - raw: the little endian bytes
+/// - packed: the values minus the minimum packed to the width of the largest
+/// - delta: packed zigzag differences between consecutive values
+/// - RLE: the number of runs of equal values, their packed values and packed lengths
+/// - dictionary: the number of distinct values, the packed sorted values and packed indices
+///
+/// A single value is written raw without a tag. Columns are followed by zeros up to
+/// [`min_column_size`].
+#[derive(Default)]
+pub struct IntCodec<T> {
+    raw: PrimitiveCodec<T>,
+}
+
+fn bits_for(range: u64) -> u32 {
+    u64::BITS - range.leading_zeros()
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn packed_size(n: usize, min: u64, max: u64) -> usize {
+    PACKED_HEADER_SIZE + (n * bits_for(max.wrapping_sub(min)) as usize).div_ceil(8)
+}
+
+/// Writes the minimum and width followed by `values - min` packed to that width.
+fn write_packed(values: impl Iterator<Item = u64> + Clone, out: &mut Output) {
+    let (min, max) = values
+        .clone()
+        .fold((u64::MAX, 0), |(min, max), v| (min.min(v), max.max(v)));
+    let min = min.min(max);
+    let bits = bits_for(max - min);
+    out.extend_from_slice(&min.to_le_bytes());
+    out.push(bits as u8);
+    let mut writer = BitWriter::new(out);
+    for v in values {
+        writer.write(v - min, bits);
+    }
+    writer.finish();
+}
+
+/// Reads `n` values written by [`write_packed`].
+fn read_packed<'a>(input: &mut &'a [u8], n: usize) -> Result<impl Iterator<Item = u64> + 'a> {
+    let header = consume_byte_arrays(input, PACKED_HEADER_SIZE, 1)?;
+    let min = u64::from_le_bytes(header[..8].try_into().unwrap());
+    let bits = header[8] as u32;
+    if bits > u64::BITS {
+        return err("invalid packed width");
+    }
+    let total_bits = n
+        .checked_mul(bits as usize)
+        .ok_or_else(|| error("length overflow"))?;
+    let mut reader = BitReader::new(consume_byte_arrays(input, total_bits.div_ceil(8), 1)?);
+    // Safety: consumed enough bytes for `n` values above.
+    Ok((0..n).map(move |_| min.wrapping_add(unsafe { reader.read_unchecked(bits) })))
+}
+
+fn read_count(input: &mut &[u8], n: usize) -> Result<usize> {
+    let bytes = consume_byte_arrays(input, COUNT_SIZE, 1)?;
+    let count = u64::from_le_bytes(bytes.try_into().unwrap());
+    if count > n as u64 {
+        return err("invalid count");
+    }
+    Ok(count as usize)
+}
+
+impl<T: Int> IntCodec<T> {
+    const BITS: u32 = size_of::<T>() as u32 * 8;
+    /// Flipping the sign bit orders signed values like unsigned ones.
+    const SIGN: u64 = if T::SIGNED { 1 << (Self::BITS - 1) } else { 0 };
+
+    #[inline(always)]
+    unsafe fn load(erased: *const u8) -> u64 {
+        let mut v = 0u64;
+        // TODO swap_bytes on big endian.
+        core::ptr::copy_nonoverlapping(erased, &mut v as *mut u64 as *mut u8, size_of::<T>());
+        v ^ Self::SIGN
+    }
+
+    #[inline(always)]
+    unsafe fn store(v: u64, erased: *mut u8) {
+        let v = v ^ Self::SIGN;
+        // TODO swap_bytes on big endian.
+        core::ptr::copy_nonoverlapping(&v as *const u64 as *const u8, erased, size_of::<T>());
+    }
+
+    /// Zigzag difference which wraps at [`Self::BITS`].
+    fn delta(prev: u64, v: u64) -> u64 {
+        let shift = u64::BITS - Self::BITS;
+        zigzag(((v.wrapping_sub(prev) << shift) as i64) >> shift)
+    }
+
+    fn undelta(prev: u64, delta: u64) -> u64 {
+        prev.wrapping_add(unzigzag(delta) as u64) & mask(Self::BITS)
+    }
+
+    /// Returns the tag of the smallest encoding of 2 or more `values`, its size (without the tag
+    /// or padding) and the sorted distinct values (for the dictionary). The sizes are computed in
+    /// one pass (and a sort for the dictionary) without encoding.
+    fn smallest_encoding(values: &[u64]) -> (u8, usize, Vec<u64>) {
+        let n = values.len();
+        let (mut min, mut max) = (u64::MAX, 0);
+        let (mut delta_min, mut delta_max) = (u64::MAX, 0);
+        // Runs are written as their length minus 1.
+        let (mut runs, mut run, mut min_run, mut max_run) = (0, 0, u64::MAX, 0);
+        let mut prev = 0;
+        for (i, &v) in values.iter().enumerate() {
+            (min, max) = (min.min(v), max.max(v));
+            let delta = Self::delta(prev, v);
+            (delta_min, delta_max) = (delta_min.min(delta), delta_max.max(delta));
+            if i != 0 && v == prev {
+                run += 1;
+            } else {
+                if i != 0 {
+                    (min_run, max_run) = (min_run.min(run), max_run.max(run));
+                }
+                runs += 1;
+                run = 0;
+            }
+            prev = v;
+        }
+        (min_run, max_run) = (min_run.min(run), max_run.max(run));
+        let mut distinct = values.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let sizes = [
+            (RAW, n * size_of::<T>()),
+            (PACKED, packed_size(n, min, max)),
+            (DELTA, packed_size(n, delta_min, delta_max)),
+            (
+                RLE,
+                COUNT_SIZE + packed_size(runs, min, max) + packed_size(runs, min_run, max_run),
+            ),
+            (
+                DICTIONARY,
+                COUNT_SIZE
+                    + packed_size(distinct.len(), min, max)
+                    + packed_size(n, 0, distinct.len() as u64 - 1),
+            ),
+        ];
+        let (tag, size) = sizes.into_iter().min_by_key(|&(t, s)| (s, t)).unwrap();
+        (tag, size, distinct)
+    }
+
+    /// Reads a column of `n` tagged values (other than raw) passing them to `f`. Checked, so it
+    /// validates too.
+    fn read_column(input: &mut &[u8], n: usize, tag: u8, mut f: impl FnMut(u64)) -> Result<()> {
+        match tag {
+            PACKED => read_packed(input, n)?.for_each(f),
+            DELTA => {
+                let mut prev = 0;
+                for delta in read_packed(input, n)? {
+                    prev = Self::undelta(prev, delta);
+                    f(prev);
+                }
+            }
+            RLE => {
+                let runs = read_count(input, n)?;
+                let values = read_packed(input, runs)?;
+                let mut remaining = n;
+                for (v, length) in values.zip(read_packed(input, runs)?) {
+                    let length = length
+                        .checked_add(1)
+                        .filter(|&l| l <= remaining as u64)
+                        .ok_or_else(|| error("invalid run length"))?;
+                    remaining -= length as usize;
+                    (0..length).for_each(|_| f(v));
+                }
+                if remaining != 0 {
+                    return err("invalid run length");
+                }
+            }
+            DICTIONARY => {
+                let count = read_count(input, n)?;
+                let dictionary: Vec<u64> = read_packed(input, count)?.collect();
+                for index in read_packed(input, n)? {
+                    let Some(&v) = usize::try_from(index).ok().and_then(|i| dictionary.get(i))
+                    else {
+                        return err("invalid dictionary index");
+                    };
+                    f(v);
+                }
+            }
+            _ => return err("invalid integer encoding"),
+        }
+        Ok(())
+    }
+}
+
+impl<T: Int> Encoder for IntCodec<T> {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        self.raw.encode_one(erased, out);
+    }
+
+    fn instruction(&self, offset: usize) -> Option<Instruction> {
+        self.raw.instruction(offset)
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        self.encode_many_strided(erased, size_of::<T>(), out);
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        let n = values.len();
+        if n <= 1 {
+            return self.raw.encoded_size(values, out_len, exact);
+        }
+        let size = if exact {
+            let values: Vec<u64> = values.iter().map(|p| Self::load(p)).collect();
+            1 + Self::smallest_encoding(&values).1
+        } else {
+            1 // Just the tag.
+        };
+        out_len + size.max(min_column_size(n))
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        let n = erased.len();
+        if n <= 1 {
+            return self.raw.encode_many_strided(erased, stride, out);
+        }
+        let src = erased as *const u8;
+        let values: Vec<u64> = (0..n)
+            .map(|i| Self::load(src.byte_add(i * stride)))
+            .collect();
+
+        let (tag, _, distinct) = Self::smallest_encoding(&values);
+
+        let start = out.position();
+        out.push(tag);
+        match tag {
+            RAW => self.raw.encode_many_strided(erased, stride, out),
+            PACKED => write_packed(values.iter().copied(), out),
+            DELTA => {
+                let prevs = core::iter::once(0).chain(values.iter().copied());
+                let deltas = prevs.zip(&values).map(|(prev, &v)| Self::delta(prev, v));
+                write_packed(deltas, out);
+            }
+            RLE => {
+                let (run_values, run_lengths): (Vec<_>, Vec<_>) = values
+                    .chunk_by(|a, b| a == b)
+                    .map(|run| (run[0], run.len() as u64 - 1))
+                    .unzip();
+                out.extend_from_slice(&(run_values.len() as u64).to_le_bytes());
+                write_packed(run_values.into_iter(), out);
+                write_packed(run_lengths.into_iter(), out);
+            }
+            DICTIONARY => {
+                out.extend_from_slice(&(distinct.len() as u64).to_le_bytes());
+                write_packed(distinct.iter().copied(), out);
+                let indices = values
+                    .iter()
+                    .map(|v| distinct.binary_search(v).unwrap() as u64);
+                write_packed(indices, out);
+            }
+            _ => unreachable!(),
+        }
+        out.extend_zeros(min_column_size(n).saturating_sub(out.position() - start));
+    }
+}
+
+impl<T: Int> Decoder for IntCodec<T> {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        if length <= 1 {
+            return self.raw.validate(input, length);
+        }
+        // Checked before reading the column, which takes time proportional to `length`.
+        let min_size = min_column_size(length);
+        consume_byte_arrays(&mut { *input }, min_size, 1)?;
+        let start = input.len();
+        match consume_byte_arrays(input, 1, 1)?[0] {
+            RAW => return self.raw.validate(input, length),
+            tag => Self::read_column(input, length, tag, |_| {})?,
+        }
+        let padding = min_size.saturating_sub(start - input.len());
+        if consume_byte_arrays(input, padding, 1)?
+            .iter()
+            .any(|&b| b != 0)
+        {
+            return err("invalid padding");
+        }
+        Ok(())
+    }
+
+    fn can_decode_into(&self) -> bool {
+        true
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        self.raw.decode_one(input, erased);
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        self.decode_many_strided(input, erased, size_of::<T>());
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        let n = erased.len();
+        if n <= 1 {
+            return self.raw.decode_many_strided(input, erased, stride);
+        }
+        let start = input.len();
+        match consume_byte_arrays_unchecked(input, 1, 1)[0] {
+            RAW => self.raw.decode_many_strided(input, erased, stride),
+            tag => {
+                let mut dst = erased as *mut u8;
+                let result = Self::read_column(input, n, tag, |v| {
+                    Self::store(v, dst);
+                    dst = dst.byte_add(stride);
+                });
+                // Safety: validated the column.
+                result.unwrap_unchecked();
+                let padding = min_column_size(n).saturating_sub(start - input.len());
+                consume_byte_arrays_unchecked(input, padding, 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::error::{error, take_needed_end};
+use crate::options::EncodeOptions;
+use crate::output::Output;
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+use facet_core::Facet;
+use std::io::{self, Read, Write};
+
+/// The reader reads at least this much (or the rest of the message) at a time.
+const MIN_READ_SIZE: usize = 4096;
+
+/// Serializes a `T:` [`Facet`] to `writer`, preceded by its length as a `u64`. Large byte columns
+/// are written straight from `t` instead of being copied to a buffer first.
+///
+/// Many small writes are made, so `writer` should be buffered.
+pub fn serialize_to_writer<'facet, T: Facet<'facet> + ?Sized>(
+    writer: impl Write,
+    t: &T,
+) -> io::Result<()> {
+    serialize_to_writer_with(writer, t, EncodeOptions::default())
+}
+
+/// Like [`serialize_to_writer`] with [`EncodeOptions`].
+pub fn serialize_to_writer_with<'facet, T: Facet<'facet> + ?Sized>(
+    mut writer: impl Write,
+    t: &T,
+    options: EncodeOptions,
+) -> io::Result<()> {
+    let codec = crate::reflect(T::SHAPE, options);
+    // Safety: reflected from `T`.
+    let size = unsafe { crate::serialize::encoded_size_with_codec(codec, t, true) } as u64;
+    writer.write_all(&size.to_le_bytes())?;
+
+    let mut buffer = Vec::new();
+    let mut out = Output::with_writer(&mut buffer, &mut writer);
+    // Safety: reflected from `T`.
+    unsafe { codec.encode_one(t as *const T as *const u8, &mut out) };
+    let written = out.finish()?;
+    debug_assert_eq!(written + buffer.len() as u64, size);
+    writer.write_all(&buffer)
+}
+
+/// Deserializes a `T:` [`Facet`] written by [`serialize_to_writer`] from `reader`, reading
+/// exactly its bytes. The input is only buffered as far as validating it requires, so a length
+/// which is larger than the input doesn't allocate more than the input.
+///
+/// Decoding errors are returned as [`io::ErrorKind::InvalidData`] wrapping an
+/// [`Error`](crate::Error).
+pub fn deserialize_from_reader<T: for<'facet> Facet<'facet>>(reader: impl Read) -> io::Result<T> {
+    deserialize_from_reader_with(reader, EncodeOptions::default())
+}
+
+/// Like [`deserialize_from_reader`] for bytes written by [`serialize_to_writer_with`] and the
+/// same [`EncodeOptions`].
+pub fn deserialize_from_reader_with<T: for<'facet> Facet<'facet>>(
+    mut reader: impl Read,
+    options: EncodeOptions,
+) -> io::Result<T> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let codec = crate::reflect(T::SHAPE, options);
+    let mut size = [0; 8];
+    reader.read_exact(&mut size)?;
+    let size = u64::from_le_bytes(size);
+
+    let mut buffer = Vec::new();
+    loop {
+        let mut validated = buffer.as_slice();
+        take_needed_end();
+        match codec.validate(&mut validated, 1) {
+            Ok(()) if validated.is_empty() && buffer.len() as u64 == size => break,
+            Ok(()) => return Err(invalid(error("Expected EOF"))),
+            Err(e) => {
+                // Validation records where it needed the input to end if it needs more input,
+                // which is read unless the message doesn't have it.
+                let Some(end) = take_needed_end() else {
+                    return Err(invalid(e));
+                };
+                let needed = (end - buffer.as_ptr() as usize) as u64;
+                if buffer.len() as u64 == size || needed > size {
+                    return Err(invalid(e));
+                }
+                let target = needed
+                    .max(buffer.len() as u64 * 2)
+                    .max(MIN_READ_SIZE as u64)
+                    .min(size);
+                // Grows with the input instead of trusting the size.
+                (&mut reader)
+                    .take(target - buffer.len() as u64)
+                    .read_to_end(&mut buffer)?;
+                if (buffer.len() as u64) < target {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+    }
+
+    let mut uninit = MaybeUninit::<T>::uninit();
+    let mut decoded = buffer.as_slice();
+    // Safety: reflected from `T` and validated.
+    unsafe { codec.decode_one(&mut decoded, uninit.as_mut_ptr() as *mut u8) };
+    debug_assert!(decoded.is_empty());
+    Ok(unsafe { uninit.assume_init() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benches::Vertex;
+    use alloc::vec;
+    use core::fmt::Debug;
+    use facet::Facet;
+
+    #[test]
+    fn test_io() {
+        use crate::{
+            deserialize_from_reader, deserialize_from_reader_with, serialize_to_writer,
+            serialize_to_writer_with, Optimize,
+        };
+        use std::io::{ErrorKind, Write};
+
+        fn write<T: for<'facet> Facet<'facet>>(t: &T, options: EncodeOptions) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            serialize_to_writer_with(&mut bytes, t, options).unwrap();
+            let mut expected = (crate::encoded_size_with(t, options) as u64)
+                .to_le_bytes()
+                .to_vec();
+            expected.extend(crate::serialize_with(t, options));
+            assert_eq!(bytes, expected);
+            bytes
+        }
+        fn roundtrip_io<T: for<'facet> Facet<'facet> + Debug + PartialEq>(t: &T) {
+            let align = EncodeOptions {
+                align: true,
+                ..Default::default()
+            };
+            let size = EncodeOptions {
+                optimize: Optimize::Size,
+                ..Default::default()
+            };
+            for options in [EncodeOptions::default(), align, size] {
+                let bytes = write(t, options);
+                let decoded: T = deserialize_from_reader_with(bytes.as_slice(), options).unwrap();
+                assert_eq!(&decoded, t);
+            }
+        }
+
+        #[derive(Debug, PartialEq, Facet)]
+        struct Column {
+            flag: u8,
+            bytes: Vec<u8>,
+            ints: Vec<u32>,
+            #[facet(bitcode(float = xor))]
+            floats: Vec<f64>,
+        }
+        let column = |n: usize| Column {
+            flag: n as u8,
+            bytes: (0..n).map(|i| i as u8).collect(),
+            ints: (0..n as u32).collect(),
+            floats: (0..n).map(|i| i as f64 / 3.0).collect(),
+        };
+        roundtrip_io(&5u32);
+        roundtrip_io(&column(0));
+        roundtrip_io(&column(100));
+        roundtrip_io(&column(10_001));
+        roundtrip_io(&vec![column(3), column(5000), column(7)]);
+        roundtrip_io(&(0..10).map(Vertex::new).collect::<Vec<_>>());
+
+        // Large byte columns are written from the source without being copied.
+        struct Writes(Vec<*const u8>);
+        impl Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.as_ptr());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let large = column(10_000);
+        let mut writes = Writes(Vec::new());
+        serialize_to_writer(&mut writes, &large).unwrap();
+        assert!(writes.0.contains(&large.ints.as_ptr().cast()));
+
+        // Reads exactly one message from a stream.
+        let mut stream = write(&column(5000), EncodeOptions::default());
+        stream.extend(write(&column(2), EncodeOptions::default()));
+        let mut reader = stream.as_slice();
+        assert_eq!(
+            deserialize_from_reader::<Column>(&mut reader).unwrap(),
+            column(5000)
+        );
+        assert_eq!(
+            deserialize_from_reader::<Column>(&mut reader).unwrap(),
+            column(2)
+        );
+        assert!(reader.is_empty());
+
+        let bytes = write(&column(100), EncodeOptions::default());
+        let result = deserialize_from_reader::<Column>(&bytes[..bytes.len() - 1]);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        let result = deserialize_from_reader::<Column>(&bytes[..]);
+        assert!(result.is_ok());
+        let result = deserialize_from_reader::<(u8, Column)>(&bytes[..]);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // Lengths which need more bytes than the message has are rejected before reading them.
+        let mut lying = 12u64.to_le_bytes().to_vec();
+        lying.extend(1_000_000u32.to_le_bytes());
+        lying.extend([0; 8]);
+        let result = deserialize_from_reader::<Vec<u32>>(lying.as_slice());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        // A size which is larger than the input fails once the input ends.
+        lying[..8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let result = deserialize_from_reader::<Vec<u32>>(lying.as_slice());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        // A message must be exactly as long as its size.
+        let mut long = 5u64.to_le_bytes().to_vec();
+        long.extend([1, 0, 0, 0, 0]);
+        let result = deserialize_from_reader::<u32>(long.as_slice());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
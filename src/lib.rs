@@ -1,32 +1,74 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-#![feature(alloc_layout_extra)] // TODO remove.
 #![cfg_attr(test, feature(test))]
 extern crate alloc;
 #[cfg(test)]
 extern crate test;
 
+mod align;
+#[cfg(feature = "std")]
+mod arena;
+mod attribute;
 #[cfg(test)]
 mod benches;
+mod bits;
 #[cfg(feature = "std")]
 mod cache;
+#[cfg(feature = "std")]
+mod chunked;
 mod codec;
+#[cfg(feature = "compress")]
+mod compress;
 mod consume;
 mod decoder;
 mod deserialize;
 mod encoder;
+mod enum_;
 mod error;
+mod float;
+#[cfg(feature = "std")]
+mod frame;
+mod int;
+#[cfg(feature = "std")]
+mod io;
+mod options;
+mod output;
+mod plan;
+mod pointer;
 mod primitive;
 #[rustfmt::skip]
 #[allow(clippy::useless_conversion)]
 #[allow(clippy::question_mark)]
 mod raw_vec_fork;
+mod scratch;
 mod serialize;
 mod slice;
+mod sparse;
+mod strided;
 mod struct_;
+mod typed;
 
+#[cfg(feature = "std")]
+pub use crate::arena::{deserialize_in, Arena};
+#[cfg(feature = "std")]
+pub use crate::attribute::register_field_attributes;
+#[cfg(feature = "std")]
+pub use crate::chunked::{ChunkReader, ChunkWriter};
+#[cfg(feature = "compress")]
+pub use crate::compress::{deserialize_compressed, serialize_compressed};
 pub use crate::error::Error;
-pub use deserialize::deserialize;
-pub use serialize::{serialize, serialize_into};
+#[cfg(feature = "std")]
+pub use crate::frame::{FrameOptions, FrameReader, FrameWriter, LengthPrefix};
+#[cfg(feature = "std")]
+pub use crate::io::{
+    deserialize_from_reader, deserialize_from_reader_with, serialize_to_writer,
+    serialize_to_writer_with,
+};
+pub use deserialize::{deserialize, deserialize_into, deserialize_into_with, deserialize_with};
+pub use options::{EncodeOptions, Optimize};
+pub use serialize::{
+    encoded_size, encoded_size_with, serialize, serialize_into, serialize_into_with, serialize_with,
+};
+pub use typed::TypedCodec;
 
 #[cfg(feature = "std")]
 pub(crate) use cache::reflect;
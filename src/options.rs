@@ -0,0 +1,20 @@
+/// Whether to encode as fast as possible or as small as possible.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Optimize {
+    /// Copy integers as is (which is often done in place).
+    #[default]
+    Speed,
+    /// Choose the smallest encoding of each integer column (raw, packed, delta, RLE or dictionary).
+    Size,
+}
+
+/// Options for [`serialize_with`](crate::serialize_with). Output must be deserialized with
+/// [`deserialize_with`](crate::deserialize_with) and the same options.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct EncodeOptions {
+    pub optimize: Optimize,
+    /// Pad each column of (uncompressed) integers, floats and chars to their alignment relative to
+    /// the start of the output, so that `&[T]` of them can be borrowed from an input which is
+    /// aligned to 8 (e.g. a memory-mapped file).
+    pub align: bool,
+}
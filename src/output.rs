@@ -0,0 +1,124 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+/// Columns of at least this many bytes are written straight from their source by an [`Output`]
+/// with a writer.
+#[cfg(feature = "std")]
+pub const MIN_DIRECT_SIZE: usize = 4096;
+
+/// What encoders write to: a buffer, and (for [`serialize_to_writer`](crate::serialize_to_writer))
+/// a writer that large byte columns are written to directly instead of being copied to the buffer.
+///
+/// Encoders can only append, since what was written to the writer can't be changed afterwards.
+pub struct Output<'a> {
+    buffer: &'a mut Vec<u8>,
+    #[cfg(feature = "std")]
+    writer: Option<Writer<'a>>,
+}
+
+#[cfg(feature = "std")]
+struct Writer<'a> {
+    writer: &'a mut dyn Write,
+    /// Number of bytes written so far, always a multiple of [`MAX_ALIGN`](crate::align::MAX_ALIGN)
+    /// so the buffer keeps the alignment of the position.
+    written: u64,
+    /// The first error, after which nothing is written.
+    result: io::Result<()>,
+}
+
+impl<'a> Output<'a> {
+    /// Appends to `buffer`.
+    pub fn new(buffer: &'a mut Vec<u8>) -> Self {
+        Self {
+            buffer,
+            #[cfg(feature = "std")]
+            writer: None,
+        }
+    }
+
+    /// Appends to `buffer` (which must be empty) after writing large byte columns to `writer`
+    /// directly. The rest of the output is left in `buffer`, see [`Self::finish`].
+    #[cfg(feature = "std")]
+    pub fn with_writer(buffer: &'a mut Vec<u8>, writer: &'a mut dyn Write) -> Self {
+        debug_assert!(buffer.is_empty());
+        Self {
+            buffer,
+            writer: Some(Writer {
+                writer,
+                written: 0,
+                result: Ok(()),
+            }),
+        }
+    }
+
+    /// Returns the number of bytes written to the writer, or its first error.
+    #[cfg(feature = "std")]
+    pub fn finish(self) -> io::Result<u64> {
+        let Writer {
+            written, result, ..
+        } = self.writer.unwrap();
+        result.map(|()| written)
+    }
+
+    /// Returns the length of the output so far, including what was written to the writer.
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        #[cfg(feature = "std")]
+        if let Some(writer) = &self.writer {
+            return writer.written as usize + self.buffer.len();
+        }
+        self.buffer.len()
+    }
+
+    /// Whether [`Self::extend_from_slice`] writes at least [`MIN_DIRECT_SIZE`] bytes directly.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    pub fn writes_directly(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, byte: u8) {
+        self.buffer.push(byte);
+    }
+
+    #[inline(always)]
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "std")]
+        if bytes.len() >= MIN_DIRECT_SIZE && self.writes_directly() {
+            return self.write_direct(bytes);
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn extend_zeros(&mut self, len: usize) {
+        self.buffer.resize(self.buffer.len() + len, 0);
+    }
+
+    /// Appends `len` bytes which `f` writes to the pointer it's passed.
+    /// Safety: `f` must initialize `len` bytes.
+    #[inline(always)]
+    pub unsafe fn extend_with(&mut self, len: usize, f: impl FnOnce(*mut u8)) {
+        self.buffer.reserve(len);
+        f(self.buffer.as_mut_ptr_range().end);
+        self.buffer.set_len(self.buffer.len() + len);
+    }
+
+    /// Writes the buffer followed by `bytes`, leaving the buffer with the unwritten remainder.
+    #[cfg(feature = "std")]
+    #[cold]
+    fn write_direct(&mut self, bytes: &[u8]) {
+        use crate::align::MAX_ALIGN;
+        let writer = self.writer.as_mut().unwrap();
+        let (direct, rest) =
+            bytes.split_at(bytes.len() - (self.buffer.len() + bytes.len()) % MAX_ALIGN);
+        if writer.result.is_ok() {
+            writer.result = (writer.writer.write_all(self.buffer))
+                .and_then(|()| writer.writer.write_all(direct));
+        }
+        writer.written += (self.buffer.len() + direct.len()) as u64;
+        self.buffer.clear();
+        self.buffer.extend_from_slice(rest);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::error::{err, Result};
+use crate::output::Output;
+use crate::struct_::StructField;
+use alloc::vec::Vec;
+
+/// One step of a [`Plan`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// Copy `size` bytes from `offset` which are valid for any bit pattern.
+    Copy { offset: usize, size: usize },
+    /// Copy a `bool` from `offset` which must be 0 or 1.
+    Bool { offset: usize },
+    /// Copy a `char` from `offset` which must be a valid `char`.
+    Char { offset: usize },
+    /// Call the codec of `fields[field]` at `offset`.
+    Codec { offset: usize, field: usize },
+}
+
+impl Instruction {
+    /// Offset and size of a copy, `None` for [`Instruction::Codec`].
+    #[inline(always)]
+    fn copy(self) -> Option<(usize, usize)> {
+        match self {
+            Self::Copy { offset, size } => Some((offset, size)),
+            Self::Bool { offset } => Some((offset, size_of::<bool>())),
+            Self::Char { offset } => Some((offset, size_of::<char>())),
+            Self::Codec { .. } => None,
+        }
+    }
+}
+
+/// A struct's fields compiled into a flat list of instructions, so encoding or decoding one
+/// struct is a loop instead of a virtual call per field.
+pub struct Plan {
+    instructions: Vec<Instruction>,
+    /// Size of the encoding if there are only copies, which lets validation be a single length
+    /// check and the `checks`.
+    fixed_size: Option<usize>,
+    /// [`Instruction::Bool`]s and [`Instruction::Char`]s with their position in the encoding (if
+    /// `fixed_size` is `Some`).
+    checks: Vec<(usize, Instruction)>,
+}
+
+impl Plan {
+    /// Compiles `fields`, merging copies of adjacent fields.
+    pub fn new(fields: &[StructField]) -> Self {
+        let mut instructions: Vec<Instruction> = Vec::with_capacity(fields.len());
+        for (i, field) in fields.iter().enumerate() {
+            let instruction =
+                field
+                    .codec()
+                    .instruction(field.offset())
+                    .unwrap_or(Instruction::Codec {
+                        offset: field.offset(),
+                        field: i,
+                    });
+            match (instructions.last_mut(), instruction) {
+                (
+                    Some(Instruction::Copy { offset, size }),
+                    Instruction::Copy {
+                        offset: next_offset,
+                        size: next_size,
+                    },
+                ) if *offset + *size == next_offset => *size += next_size,
+                _ => instructions.push(instruction),
+            }
+        }
+        let fixed_size: Option<usize> = instructions
+            .iter()
+            .map(|instruction| instruction.copy().map(|(_, size)| size))
+            .sum();
+        let mut checks = Vec::new();
+        if fixed_size.is_some() {
+            let mut position = 0;
+            for &instruction in &instructions {
+                if !matches!(instruction, Instruction::Copy { .. }) {
+                    checks.push((position, instruction));
+                }
+                // Safety: only `Instruction::Codec` isn't a copy.
+                position += unsafe { instruction.copy().unwrap_unchecked() }.1;
+            }
+        }
+        Self {
+            instructions,
+            fixed_size,
+            checks,
+        }
+    }
+
+    /// Safety: `erased` must be a valid instance of the struct that `fields` belong to.
+    #[inline(always)]
+    pub unsafe fn encode_one(&self, fields: &[StructField], erased: *const u8, out: &mut Output) {
+        if let Some(fixed_size) = self.fixed_size {
+            return out.extend_with(fixed_size, |mut dst| {
+                for &instruction in &self.instructions {
+                    let (offset, size) = instruction.copy().unwrap_unchecked();
+                    // TODO swap_bytes on big endian.
+                    core::ptr::copy_nonoverlapping(erased.byte_add(offset), dst, size);
+                    dst = dst.byte_add(size);
+                }
+            });
+        }
+        for &instruction in &self.instructions {
+            if let Instruction::Codec { offset, field } = instruction {
+                let codec = fields.get_unchecked(field).codec();
+                codec.encode_one(erased.byte_add(offset), out);
+            } else {
+                let (offset, size) = instruction.copy().unwrap_unchecked();
+                // TODO swap_bytes on big endian.
+                out.extend_from_slice(core::slice::from_raw_parts(erased.byte_add(offset), size));
+            }
+        }
+    }
+
+    /// Like [`Decoder::validate`](crate::decoder::Decoder::validate) with a length of 1.
+    pub fn validate_one(&self, fields: &[StructField], input: &mut &[u8]) -> Result<()> {
+        if let Some(fixed_size) = self.fixed_size {
+            let bytes = consume_byte_arrays(input, fixed_size, 1)?;
+            for &(position, instruction) in &self.checks {
+                Self::check(instruction, &bytes[position..])?;
+            }
+            return Ok(());
+        }
+        for &instruction in &self.instructions {
+            if let Instruction::Codec { field, .. } = instruction {
+                // Safety: compiled from `fields`.
+                unsafe { fields.get_unchecked(field) }
+                    .codec()
+                    .validate(input, 1)?;
+            } else {
+                // Safety: only `Instruction::Codec` isn't a copy.
+                let (_, size) = unsafe { instruction.copy().unwrap_unchecked() };
+                Self::check(instruction, consume_byte_arrays(input, size, 1)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the bytes copied by `instruction` have a valid bit pattern.
+    #[inline(always)]
+    fn check(instruction: Instruction, bytes: &[u8]) -> Result<()> {
+        let valid = match instruction {
+            Instruction::Bool { .. } => bytes[0] <= 1,
+            Instruction::Char { .. } => {
+                char::from_u32(u32::from_le_bytes(bytes[..4].try_into().unwrap())).is_some()
+            }
+            _ => true,
+        };
+        if !valid {
+            return err("invalid bit pattern");
+        }
+        Ok(())
+    }
+
+    /// Safety: `input` must have been validated by [`Self::validate_one`].
+    #[inline(always)]
+    pub unsafe fn decode_one(&self, fields: &[StructField], input: &mut &[u8], erased: *mut u8) {
+        if let Some(fixed_size) = self.fixed_size {
+            let mut src = consume_byte_arrays_unchecked(input, fixed_size, 1).as_ptr();
+            for &instruction in &self.instructions {
+                let (offset, size) = instruction.copy().unwrap_unchecked();
+                // TODO swap_bytes on big endian.
+                core::ptr::copy_nonoverlapping(src, erased.byte_add(offset), size);
+                src = src.byte_add(size);
+            }
+            return;
+        }
+        for &instruction in &self.instructions {
+            if let Instruction::Codec { offset, field } = instruction {
+                let codec = fields.get_unchecked(field).codec();
+                codec.decode_one(input, erased.byte_add(offset));
+            } else {
+                let (offset, size) = instruction.copy().unwrap_unchecked();
+                let bytes = consume_byte_arrays_unchecked(input, size, 1);
+                // TODO swap_bytes on big endian.
+                core::ptr::copy_nonoverlapping(bytes.as_ptr(), erased.byte_add(offset), size);
+            }
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use crate::codec::DynamicCodec;
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, Values};
+use crate::error::{err, Result};
+use crate::output::Output;
+use crate::scratch::Scratch;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::any::TypeId;
+use facet_core::{BorrowFn, CloneIntoFn, NewIntoFn, PtrConst, PtrMut, PtrUninit};
+
+type IndexInt = u32;
+
+/// The allocations of one type of shared pointer seen so far in a message.
+#[derive(Default)]
+struct Table {
+    /// The index of each pointee written so far (when encoding or measuring).
+    indices: BTreeMap<*const u8, IndexInt>,
+    /// The number of allocations so far (when validating).
+    count: usize,
+    /// A copy (which doesn't own a reference) of the first pointer decoded to each allocation so
+    /// far, which repeats are cloned from.
+    decoded: Vec<*const u8>,
+}
+
+/// Tables by the `TypeId` of the pointer.
+type Tables = BTreeMap<TypeId, Table>;
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The tables of the [`scope`] running on this thread.
+    static TABLES: core::cell::Cell<*mut Tables> = const { core::cell::Cell::new(core::ptr::null_mut()) };
+}
+
+/// Runs `f` with new tables, so the shared pointers it encodes, measures, validates or decodes
+/// only refer back to allocations within it.
+#[inline]
+pub fn scope<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "std")]
+    {
+        /// Restores the previous tables, even if `f` panics.
+        struct Restore(*mut Tables);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                TABLES.set(self.0);
+            }
+        }
+        let mut tables = Tables::new();
+        let _restore = Restore(TABLES.replace(&mut tables));
+        f()
+    }
+    #[cfg(not(feature = "std"))]
+    f()
+}
+
+/// Calls `f` with the table of the pointer `key` in the current [`scope`]. `f` mustn't encode or
+/// decode, which could borrow the tables again.
+fn with_table<R>(key: TypeId, f: impl FnOnce(&mut Table) -> R) -> R {
+    #[cfg(feature = "std")]
+    {
+        let tables = TABLES.get();
+        assert!(!tables.is_null(), "shared pointer outside of a scope");
+        // Safety: set by `scope` which outlives this call, and `f` doesn't borrow it again.
+        f(unsafe { &mut *tables }.entry(key).or_default())
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = (key, f);
+        unreachable!("#[facet(bitcode(shared))] requires std");
+    }
+}
+
+/// Codec for `Box<T>`, `Arc<T>` and `Rc<T>` which writes the pointees in a column.
+///
+/// If `shared` each distinct allocation is only written once per message (which must be wrapped
+/// in a [`ScopedCodec`]), even if it's reachable from several fields or from the pointees of other
+/// pointers. A column of indices precedes the pointees: an index equal to the number of
+/// allocations of the pointer's type so far in the message is a new allocation, a smaller one
+/// refers back to an earlier allocation.
+pub struct PointerCodec {
+    pointee: DynamicCodec,
+    pointee_layout: Layout,
+    /// Size of the pointer.
+    size: usize,
+    borrow: BorrowFn,
+    new_into: NewIntoFn,
+    /// `Some` if shared.
+    clone_into: Option<CloneIntoFn>,
+    /// The type of the pointer, which allocations are shared between.
+    key: TypeId,
+}
+
+impl PointerCodec {
+    pub fn new(
+        pointee: DynamicCodec,
+        pointee_layout: Layout,
+        size: usize,
+        borrow: BorrowFn,
+        new_into: NewIntoFn,
+        clone_into: Option<CloneIntoFn>,
+        key: TypeId,
+    ) -> Self {
+        // Decoded pointers are copied to the table as a `*const u8`.
+        assert!(clone_into.is_none() || size == size_of::<*const u8>());
+        Self {
+            pointee,
+            pointee_layout,
+            size,
+            borrow,
+            new_into,
+            clone_into,
+            key,
+        }
+    }
+
+    /// Safety: `erased` must be a valid pointer.
+    #[inline(always)]
+    unsafe fn pointee_ptr(&self, erased: *const u8) -> *const u8 {
+        (self.borrow)(PtrConst::new(erased))
+            .thin()
+            .unwrap()
+            .as_byte_ptr()
+    }
+
+    /// Gathers the pointees so they can be encoded in columns.
+    /// Safety: `pointees` must be valid to read.
+    unsafe fn gather(&self, pointees: &[*const u8]) -> Scratch {
+        let size = self.pointee_layout.size();
+        let gathered = Scratch::new(self.pointee_layout, pointees.len());
+        for (i, &pointee) in pointees.iter().enumerate() {
+            core::ptr::copy_nonoverlapping(pointee, gathered.as_ptr().byte_add(i * size), size);
+        }
+        gathered
+    }
+
+    /// Safety: `pointees` must be valid to read.
+    unsafe fn encode_pointees(&self, pointees: &[*const u8], out: &mut Output) {
+        let gathered = self.gather(pointees);
+        self.pointee.encode_many(
+            core::ptr::slice_from_raw_parts(gathered.as_ptr(), pointees.len()),
+            out,
+        );
+    }
+
+    /// Returns the pointees which are encoded, which are the new ones in the current [`scope`] if
+    /// shared.
+    /// Safety: `pointers` must be valid pointers.
+    unsafe fn pointees(&self, pointers: impl Iterator<Item = *const u8>) -> Vec<*const u8> {
+        let pointees = pointers.map(|p| self.pointee_ptr(p));
+        if self.clone_into.is_none() {
+            return pointees.collect();
+        }
+        with_table(self.key, |table| {
+            pointees
+                .filter(|&p| {
+                    let index = table.indices.len() as IndexInt;
+                    table.indices.insert(p, index).is_none()
+                })
+                .collect()
+        })
+    }
+}
+
+impl Encoder for PointerCodec {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        self.encode_many_strided(core::ptr::slice_from_raw_parts(erased, 1), self.size, out);
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        self.encode_many_strided(erased, self.size, out);
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        if self.clone_into.is_none() {
+            let src = erased as *const u8;
+            let pointers = (0..erased.len()).map(|i| src.byte_add(i * stride));
+            return self.encode_pointees(&self.pointees(pointers), out);
+        }
+
+        let src = erased as *const u8;
+        let pointees = (0..erased.len()).map(|i| self.pointee_ptr(src.byte_add(i * stride)));
+        let mut distinct = Vec::new();
+        with_table(self.key, |table| {
+            for pointee in pointees {
+                let next = table.indices.len() as IndexInt;
+                let index = *table.indices.entry(pointee).or_insert_with(|| {
+                    distinct.push(pointee);
+                    next
+                });
+                out.extend_from_slice(&index.to_le_bytes());
+            }
+        });
+        self.encode_pointees(&distinct, out);
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        let mut out_len = out_len;
+        if self.clone_into.is_some() {
+            out_len += values.len() * size_of::<IndexInt>();
+        }
+        let runs: Vec<_> = self
+            .pointees(values.iter())
+            .into_iter()
+            .map(|pointee| (pointee, 1))
+            .collect();
+        let values = Values::new(&runs, self.pointee_layout.size());
+        self.pointee.encoded_size(values, out_len, exact)
+    }
+}
+
+impl Decoder for PointerCodec {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        if self.clone_into.is_none() {
+            return self.pointee.validate(input, length);
+        }
+        let bytes = consume_byte_arrays(input, length, size_of::<IndexInt>())?;
+        let new = with_table(self.key, |table| {
+            let before = table.count;
+            for index in bytes.chunks_exact(size_of::<IndexInt>()) {
+                let index = IndexInt::from_le_bytes(index.try_into().unwrap()) as usize;
+                if index == table.count {
+                    table.count += 1;
+                } else if index > table.count {
+                    return err("invalid pointer back-reference");
+                }
+            }
+            Ok(table.count - before)
+        })?;
+        self.pointee.validate(input, new)
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        self.decode_many_strided(
+            input,
+            core::ptr::slice_from_raw_parts_mut(erased, 1),
+            self.size,
+        );
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        self.decode_many_strided(input, erased, self.size);
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        let n = erased.len();
+        let dst = erased as *mut u8;
+        let indices: Option<Vec<usize>> = self.clone_into.map(|_| {
+            consume_byte_arrays_unchecked(input, n, size_of::<IndexInt>())
+                .chunks_exact(size_of::<IndexInt>())
+                .map(|index| IndexInt::from_le_bytes(index.try_into().unwrap()) as usize)
+                .collect()
+        });
+        let new = indices.as_ref().map_or(n, |indices| {
+            let before = with_table(self.key, |table| table.decoded.len());
+            let after = indices
+                .iter()
+                .fold(before, |count, &index| count + (index == count) as usize);
+            after - before
+        });
+
+        let size = self.pointee_layout.size();
+        let decoded = Scratch::new(self.pointee_layout, new);
+        self.pointee.decode_many(
+            input,
+            core::ptr::slice_from_raw_parts_mut(decoded.as_ptr(), new),
+        );
+
+        let Some((indices, clone_into)) = indices.zip(self.clone_into) else {
+            for i in 0..n {
+                (self.new_into)(
+                    PtrUninit::new(dst.byte_add(i * stride)),
+                    PtrMut::new(decoded.as_ptr().byte_add(i * size)),
+                );
+            }
+            return;
+        };
+        with_table(self.key, |table| {
+            let mut src = decoded.as_ptr();
+            for (i, index) in indices.into_iter().enumerate() {
+                let p = dst.byte_add(i * stride);
+                if index == table.decoded.len() {
+                    (self.new_into)(PtrUninit::new(p), PtrMut::new(src));
+                    src = src.byte_add(size);
+                    table.decoded.push(*(p as *const *const u8));
+                } else {
+                    // The first pointer to the allocation is still alive in the output.
+                    let first = table.decoded.get_unchecked(index) as *const *const u8;
+                    clone_into(PtrConst::new(first as *const u8), PtrUninit::new(p));
+                }
+            }
+        });
+    }
+}
+
+/// Wraps the codec of a message with shared [`PointerCodec`]s in a [`scope`], so that each
+/// encode, measure, validate or decode of it has new tables.
+pub struct ScopedCodec(pub DynamicCodec);
+
+impl Encoder for ScopedCodec {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        scope(|| self.0.encode_one(erased, out))
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        scope(|| self.0.encode_many(erased, out))
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        scope(|| self.0.encode_many_strided(erased, stride, out))
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        scope(|| self.0.encoded_size(values, out_len, exact))
+    }
+}
+
+impl Decoder for ScopedCodec {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        scope(|| self.0.validate(input, length))
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        scope(|| self.0.decode_one(input, erased))
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        scope(|| self.0.decode_many(input, erased))
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        scope(|| self.0.decode_many_strided(input, erased, stride))
+    }
+
+    fn can_decode_into(&self) -> bool {
+        self.0.can_decode_into()
+    }
+
+    unsafe fn decode_many_strided_into(
+        &self,
+        input: &mut &[u8],
+        erased: *mut [u8],
+        stride: usize,
+        initialized: usize,
+    ) {
+        scope(|| {
+            (self.0).decode_many_strided_into(input, erased, stride, initialized);
+        })
+    }
+}
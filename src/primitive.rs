@@ -1,8 +1,10 @@
 use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
 use crate::decoder::Decoder;
-use crate::encoder::Encoder;
+use crate::encoder::{Encoder, Values};
 use crate::error::{err, Result};
-use alloc::vec::Vec;
+use crate::output::Output;
+use crate::plan::Instruction;
+use crate::strided;
 use bytemuck::{CheckedBitPattern, NoUninit};
 use core::marker::PhantomData;
 use core::mem::MaybeUninit;
@@ -13,13 +15,50 @@ pub static DUMMY_CODEC: PrimitiveCodec<u32> = PrimitiveCodec(PhantomData);
 #[derive(Default)]
 pub struct PrimitiveCodec<T>(PhantomData<fn(T)>);
 
-impl<T: NoUninit> Encoder for PrimitiveCodec<T> {
-    unsafe fn encode_one(&self, erased: *const u8, out: &mut Vec<u8>) {
+/// Types that [`PrimitiveCodec`] copies as is.
+pub trait Primitive: NoUninit + CheckedBitPattern + Default {
+    /// Whether every bit pattern is valid.
+    const INFALLIBLE: bool = true;
+
+    /// Instruction to copy (and validate) one at `offset`.
+    fn instruction(offset: usize) -> Instruction {
+        Instruction::Copy {
+            offset,
+            size: size_of::<Self>(),
+        }
+    }
+}
+
+macro_rules! impl_primitive {
+    ($($t:ty),+) => {
+        $(impl Primitive for $t {})+
+    };
+}
+impl_primitive!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Primitive for bool {
+    const INFALLIBLE: bool = false;
+
+    fn instruction(offset: usize) -> Instruction {
+        Instruction::Bool { offset }
+    }
+}
+
+impl Primitive for char {
+    const INFALLIBLE: bool = false;
+
+    fn instruction(offset: usize) -> Instruction {
+        Instruction::Char { offset }
+    }
+}
+
+impl<T: Primitive> Encoder for PrimitiveCodec<T> {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
         let erased: &[u8] = core::slice::from_raw_parts(erased, core::mem::size_of::<T>());
         out.extend_from_slice(erased); // TODO swap_bytes on big endian.
     }
 
-    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Vec<u8>) {
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
         let erased: &[u8] = core::slice::from_raw_parts(
             erased as *const u8,
             erased.len() * core::mem::size_of::<T>(),
@@ -27,27 +66,33 @@ impl<T: NoUninit> Encoder for PrimitiveCodec<T> {
         out.extend_from_slice(erased); // TODO swap_bytes on big endian.
     }
 
-    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Vec<u8>) {
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
         let dst_size = erased.len() * core::mem::size_of::<T>();
-        out.reserve(dst_size);
-
-        let mut src = erased as *const u8;
-        let mut dst = out.as_mut_ptr_range().end;
-        for _ in 0..erased.len() {
-            core::ptr::copy_nonoverlapping(src, dst, core::mem::size_of::<T>()); // TODO swap_bytes on big endian.
-            src = src.byte_add(stride);
-            dst = dst.byte_add(core::mem::size_of::<T>());
-        }
+        out.extend_with(dst_size, |dst| {
+            strided::gather(
+                core::mem::size_of::<T>(),
+                erased as *const u8,
+                stride,
+                dst,
+                erased.len(),
+            )
+        });
+    }
 
-        out.set_len(out.len() + dst_size);
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, _: bool) -> usize {
+        out_len + values.len() * core::mem::size_of::<T>()
     }
 
     fn in_place(&self) -> bool {
         true // TODO only on little endian
     }
+
+    fn instruction(&self, offset: usize) -> Option<Instruction> {
+        Some(T::instruction(offset))
+    }
 }
 
-impl<T: CheckedBitPattern> PrimitiveCodec<T> {
+impl<T: Primitive> PrimitiveCodec<T> {
     /// Safety: `bytes` must contain at least enough bytes to decode `length` primitives.
     pub unsafe fn iter<'a>(
         &'a self,
@@ -62,9 +107,12 @@ impl<T: CheckedBitPattern> PrimitiveCodec<T> {
     }
 }
 
-impl<T: CheckedBitPattern> Decoder for PrimitiveCodec<T> {
+impl<T: Primitive> Decoder for PrimitiveCodec<T> {
     fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
         let bytes = consume_byte_arrays(input, length, core::mem::size_of::<T>())?;
+        if T::INFALLIBLE {
+            return Ok(());
+        }
 
         // Safety: `bytes` contains enough bytes to decode `length` primitives.
         let iter = unsafe { self.iter(bytes, length) };
@@ -76,6 +124,10 @@ impl<T: CheckedBitPattern> Decoder for PrimitiveCodec<T> {
         Ok(())
     }
 
+    fn infallible_size(&self) -> Option<usize> {
+        T::INFALLIBLE.then_some(core::mem::size_of::<T>())
+    }
+
     unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
         let bytes = consume_byte_arrays_unchecked(input, 1, core::mem::size_of::<T>());
         core::ptr::copy_nonoverlapping(bytes.as_ptr(), erased, bytes.len());
@@ -90,13 +142,22 @@ impl<T: CheckedBitPattern> Decoder for PrimitiveCodec<T> {
 
     unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
         let bytes = consume_byte_arrays_unchecked(input, erased.len(), core::mem::size_of::<T>());
+        let dst = erased as *mut u8;
+        strided::scatter(
+            core::mem::size_of::<T>(),
+            bytes.as_ptr(),
+            dst,
+            stride,
+            erased.len(),
+        );
+    }
 
-        let mut src = bytes.as_ptr();
-        let mut dst = erased as *mut u8;
-        for _ in 0..erased.len() {
-            core::ptr::copy_nonoverlapping(src, dst, core::mem::size_of::<T>()); // TODO swap_bytes on big endian.
-            src = src.byte_add(core::mem::size_of::<T>());
-            dst = dst.byte_add(stride);
-        }
+    fn can_decode_into(&self) -> bool {
+        true
+    }
+
+    unsafe fn decode_borrowed(&self, input: &mut &[u8], length: usize) -> *const u8 {
+        // TODO big endian can't borrow.
+        consume_byte_arrays_unchecked(input, length, core::mem::size_of::<T>()).as_ptr()
     }
 }
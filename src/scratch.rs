@@ -0,0 +1,44 @@
+use core::alloc::Layout;
+
+/// Uninitialized space for `count` values, deallocated (but not dropped) on drop.
+pub struct Scratch {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Scratch {
+    pub fn new(element_layout: Layout, count: usize) -> Self {
+        let (layout, stride) = element_layout.repeat(count).unwrap();
+        debug_assert_eq!(stride, element_layout.size());
+        let ptr = if layout.size() == 0 {
+            // Dangling but aligned like RawVecInner::new_in.
+            element_layout.align() as *mut u8
+        } else {
+            // Safety: layout has a non-zero size.
+            let ptr = unsafe { alloc::alloc::alloc(layout) }; // TODO scratch allocator like rkyv?
+            if ptr.is_null() {
+                alloc::alloc::handle_alloc_error(layout);
+            }
+            ptr
+        };
+        Self { ptr, layout }
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    #[cfg(feature = "std")]
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        if self.layout.size() != 0 {
+            // Safety: allocated in Scratch::new with the same layout.
+            unsafe { alloc::alloc::dealloc(self.ptr, self.layout) };
+        }
+    }
+}
@@ -1,23 +1,84 @@
-use alloc::vec;
+use crate::codec::Codec;
+use crate::encoder::Values;
+use crate::options::EncodeOptions;
+use crate::output::Output;
 use alloc::vec::Vec;
+use core::ops::Deref;
 use facet_core::Facet;
 
 /// Serializes a `T:` [`Facet`] into a [`Vec<u8>`].
 pub fn serialize<'facet, T: Facet<'facet> + ?Sized>(t: &T) -> Vec<u8> {
-    let mut out = vec![];
-    serialize_into(&mut out, t);
-    out
+    serialize_with(t, EncodeOptions::default())
 }
 
 /// Serializes a `T:` [`Facet`] directly into a [`&mut Vec<u8>`](`Vec`).
 pub fn serialize_into<'facet, T: Facet<'facet> + ?Sized>(out: &mut Vec<u8>, t: &T) {
-    let codec = crate::reflect(T::SHAPE);
-    unsafe { codec.encode_one(t as *const T as *const u8, out) };
+    serialize_into_with(out, t, EncodeOptions::default());
+}
+
+/// Like [`serialize`] with [`EncodeOptions`].
+pub fn serialize_with<'facet, T: Facet<'facet> + ?Sized>(t: &T, options: EncodeOptions) -> Vec<u8> {
+    let codec = crate::reflect(T::SHAPE, options);
+    // Safety: reflected from `T`.
+    unsafe { serialize_with_codec(codec, t) }
+}
+
+/// Safety: `codec` must be reflected from `T`.
+pub(crate) unsafe fn serialize_with_codec<T: ?Sized>(
+    codec: impl Deref<Target = dyn Codec>,
+    t: &T,
+) -> Vec<u8> {
+    // A lower bound which doesn't measure values, the output grows if it's larger.
+    let mut out = Vec::with_capacity(encoded_size_with_codec(&*codec, t, false));
+    codec.encode_one(t as *const T as *const u8, &mut Output::new(&mut out));
+    out
+}
+
+/// Like [`serialize_into`] with [`EncodeOptions`].
+pub fn serialize_into_with<'facet, T: Facet<'facet> + ?Sized>(
+    out: &mut Vec<u8>,
+    t: &T,
+    options: EncodeOptions,
+) {
+    let codec = crate::reflect(T::SHAPE, options);
+    unsafe { codec.encode_one(t as *const T as *const u8, &mut Output::new(out)) };
+}
+
+/// Returns the length of [`serialize`]'s output, which is computed from the lengths of `t`'s
+/// collections without encoding it. Only values whose size depends on their contents (e.g.
+/// `#[facet(bitcode(float = xor))]`) take a pass over them to measure them.
+pub fn encoded_size<'facet, T: Facet<'facet> + ?Sized>(t: &T) -> usize {
+    encoded_size_with(t, EncodeOptions::default())
+}
+
+/// Like [`encoded_size`] with [`EncodeOptions`].
+pub fn encoded_size_with<'facet, T: Facet<'facet> + ?Sized>(
+    t: &T,
+    options: EncodeOptions,
+) -> usize {
+    let codec = crate::reflect(T::SHAPE, options);
+    // Safety: reflected from `T`.
+    unsafe { encoded_size_with_codec(codec, t, true) }
+}
+
+/// Returns the exact size if `exact`, otherwise a lower bound (see
+/// [`Encoder::encoded_size`](crate::encoder::Encoder::encoded_size)).
+/// Safety: `codec` must be reflected from `T`.
+pub(crate) unsafe fn encoded_size_with_codec<T: ?Sized>(
+    codec: impl Deref<Target = dyn Codec>,
+    t: &T,
+    exact: bool,
+) -> usize {
+    let runs = [(t as *const T as *const u8, 1)];
+    let values = Values::new(&runs, core::mem::size_of_val(t));
+    codec.encoded_size(values, 0, exact)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::boxed::Box;
+    use alloc::vec;
     use facet::Facet;
     use test::{black_box, Bencher};
 
@@ -91,6 +152,243 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialize_float_xor() {
+        #[derive(Facet)]
+        struct Reading {
+            #[facet(bitcode(float = xor))]
+            xor: f64,
+            #[facet(bitcode(float = auto))]
+            auto: f64,
+        }
+
+        // 0 is written as a single 0 bit, auto adds a tag.
+        assert_eq!(
+            serialize(&Reading {
+                xor: 0.0,
+                auto: 0.0
+            }),
+            vec![0, 1, 0]
+        );
+
+        let readings: Vec<_> = (0..1000)
+            .map(|i| Reading {
+                xor: 1000.0 + (i / 10) as f64 * 0.5,
+                auto: 1000.0 + (i / 10) as f64 * 0.5,
+            })
+            .collect();
+        let out = serialize(&readings);
+        assert!(out.len() < 1000 * 8 / 4, "{}", out.len());
+
+        // Auto falls back to raw when XOR isn't smaller.
+        let mut rng = 0x1234_5678_9ABC_DEF0u64;
+        let readings: Vec<_> = (0..1000)
+            .map(|_| {
+                rng = rng.wrapping_mul(6364136223846793005).wrapping_add(1);
+                let random = f64::from_bits(rng >> 2);
+                Reading {
+                    xor: 0.0,
+                    auto: random,
+                }
+            })
+            .collect();
+        let out = serialize(&readings);
+        assert_eq!(out.len(), 4 + 1000 / 8 + 1 + 1000 * 8);
+    }
+
+    #[test]
+    fn test_serialize_float_shuffle() {
+        #[derive(Facet)]
+        struct Shuffled(#[facet(bitcode(float = shuffle))] Vec<f32>);
+
+        let a = 1.5f32.to_le_bytes();
+        let b = (-3.0f32).to_le_bytes();
+        let out = serialize(&Shuffled(vec![1.5, -3.0]));
+        assert_eq!(
+            out,
+            vec![2, 0, 0, 0, a[0], b[0], a[1], b[1], a[2], b[2], a[3], b[3]]
+        );
+    }
+
+    #[test]
+    fn test_serialize_enum() {
+        #[derive(Facet)]
+        #[repr(u8)]
+        #[allow(dead_code)]
+        enum Direction {
+            North,
+            East,
+            South,
+            West,
+        }
+        use Direction::*;
+
+        // 2 bits per variant.
+        let out = serialize(&vec![North, East, South, West, West]);
+        assert_eq!(out, vec![5, 0, 0, 0, 0b11_10_01_00, 0b11]);
+
+        #[derive(Facet)]
+        #[repr(u32)]
+        #[allow(dead_code)]
+        enum Either {
+            Left(u8),
+            Right(u16),
+        }
+        let out = serialize(&vec![Either::Right(5), Either::Left(3), Either::Right(6)]);
+        assert_eq!(out, vec![3, 0, 0, 0, 0b101, 3, 5, 0, 6, 0]);
+    }
+
+    #[test]
+    fn test_serialize_shared_lengths() {
+        #[derive(Facet)]
+        struct Points {
+            x: Vec<u8>,
+            y: Vec<u8>,
+            z: Vec<u8>,
+        }
+
+        // y and z share x's length column.
+        let out = serialize(&vec![
+            Points {
+                x: vec![1],
+                y: vec![2],
+                z: vec![3],
+            },
+            Points {
+                x: vec![4, 5],
+                y: vec![6, 7],
+                z: vec![8, 9],
+            },
+        ]);
+        assert_eq!(
+            out,
+            vec![2, 0, 0, 0, 0b11, 1, 0, 0, 0, 2, 0, 0, 0, 1, 4, 5, 2, 6, 7, 3, 8, 9]
+        );
+
+        // Only z shares x's length column.
+        let out = serialize(&Points {
+            x: vec![1],
+            y: vec![],
+            z: vec![3],
+        });
+        assert_eq!(out, vec![0b10, 1, 0, 0, 0, 1, 0, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_serialize_shared_pointer() {
+        use alloc::sync::Arc;
+
+        #[derive(Facet)]
+        struct Shared(#[facet(bitcode(shared))] Vec<Arc<u8>>);
+        #[derive(Facet)]
+        struct Inline(Vec<Arc<u8>>);
+
+        let a = Arc::new(5);
+        let b = Arc::new(6);
+        let v = vec![a.clone(), a.clone(), b, a];
+        assert_eq!(
+            serialize(&Shared(v.clone())),
+            vec![4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 5, 6]
+        );
+        assert_eq!(serialize(&Inline(v)), vec![4, 0, 0, 0, 5, 5, 6, 5]);
+    }
+
+    #[test]
+    fn test_serialize_strided() {
+        #[derive(Facet)]
+        struct Strided {
+            a: u8,
+            b: u32,
+        }
+        // Not a multiple of the SIMD width.
+        let strided: Vec<_> = (0..37u32)
+            .map(|i| Strided {
+                a: (i * 3) as u8,
+                b: i,
+            })
+            .collect();
+
+        let mut expected = serialize(&strided.iter().map(|s| s.a).collect::<Vec<_>>());
+        expected.extend(strided.iter().flat_map(|s| s.b.to_le_bytes()));
+        assert_eq!(serialize(&strided), expected);
+    }
+
+    #[test]
+    fn test_serialize_tiled() {
+        #[derive(Facet)]
+        struct Wide(u8, u8, u8, u8, u8, u8, u8, u8, u8, u16);
+        // Large enough to be tiled, ending in a partial tile.
+        let wide: Vec<_> = (0..100_000u32)
+            .map(|i| {
+                let b = |j: u32| (i * 7 + j) as u8;
+                Wide(
+                    b(0),
+                    b(1),
+                    b(2),
+                    b(3),
+                    b(4),
+                    b(5),
+                    b(6),
+                    b(7),
+                    b(8),
+                    i as u16,
+                )
+            })
+            .collect();
+
+        let mut expected = serialize(&wide.iter().map(|w| w.0).collect::<Vec<_>>());
+        expected.extend(wide.iter().map(|w| w.1));
+        expected.extend(wide.iter().map(|w| w.2));
+        expected.extend(wide.iter().map(|w| w.3));
+        expected.extend(wide.iter().map(|w| w.4));
+        expected.extend(wide.iter().map(|w| w.5));
+        expected.extend(wide.iter().map(|w| w.6));
+        expected.extend(wide.iter().map(|w| w.7));
+        expected.extend(wide.iter().map(|w| w.8));
+        expected.extend(wide.iter().flat_map(|w| w.9.to_le_bytes()));
+        assert_eq!(serialize(&wide), expected);
+    }
+
+    #[test]
+    fn test_serialize_sparse() {
+        #[derive(Facet)]
+        struct Sparse(#[facet(bitcode(sparse))] u32);
+
+        // Bitmap of non-default values followed by only those values.
+        let v: Vec<_> = (0..16)
+            .map(|i| Sparse(if i == 9 { 7 } else { 0 }))
+            .collect();
+        assert_eq!(serialize(&v), vec![16, 0, 0, 0, 1, 0, 0b10, 7, 0, 0, 0]);
+
+        // Dense when no values are default.
+        assert_eq!(serialize(&vec![Sparse(7)]), vec![1, 0, 0, 0, 0, 7, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_serialize_optimize_size() {
+        use crate::{EncodeOptions, Optimize};
+        let size = |v: &Vec<u32>| {
+            serialize_with(
+                v,
+                EncodeOptions {
+                    optimize: Optimize::Size,
+                    ..Default::default()
+                },
+            )
+        };
+        let tag = |v: Vec<u32>| size(&v)[4];
+
+        // A single value is raw without a tag.
+        assert_eq!(size(&vec![5]), vec![1, 0, 0, 0, 5, 0, 0, 0]);
+        assert_eq!(tag((0..2).map(|i| i << 31).collect()), 0); // Raw.
+        assert_eq!(tag((0..100).map(|i| 1000 + i % 7).collect()), 1); // Packed.
+        assert_eq!(tag((0..100).map(|i| 1000 + i * 1000).collect()), 2); // Delta.
+        assert_eq!(tag((0..100).map(|i| (i / 50) << 30).collect()), 3); // RLE.
+        assert_eq!(tag((0..100).map(|i| (i % 3) << 30).collect()), 4); // Dictionary.
+    }
+
+    #[allow(clippy::type_complexity)]
     fn nested_slice() -> &'static [&'static [&'static [&'static [&'static [&'static [&'static [&'static [&'static [&'static [u16]]]]]]]]]]{
         let depth = 4;
         let n = 40;
@@ -146,6 +444,97 @@ mod tests {
         &*v0.leak()
     }
 
+    #[test]
+    fn test_encoded_size() {
+        use crate::{encoded_size_with, EncodeOptions, Optimize};
+        use alloc::sync::Arc;
+
+        #[derive(Facet)]
+        #[repr(u8)]
+        #[allow(dead_code)]
+        enum Shape {
+            Empty,
+            Circle(f32),
+            Polygon {
+                points: Vec<(i16, i16)>,
+                name: Vec<u8>,
+            },
+        }
+
+        #[derive(Facet)]
+        struct Sparse(#[facet(bitcode(sparse))] u8);
+
+        #[derive(Facet)]
+        struct Everything<'a> {
+            id: u32,
+            flag: bool,
+            c: char,
+            name: &'a str,
+            a: Vec<u16>,
+            b: Vec<u8>,
+            nested: Vec<Vec<u64>>,
+            shapes: Vec<Shape>,
+            // Shared pointers (which require std) are measured in deserialize's test_pointer.
+            arcs: Vec<Arc<Vec<u8>>>,
+            boxed: Box<u32>,
+            sparse: Vec<Sparse>,
+            #[facet(bitcode(float = xor))]
+            xor: Vec<f64>,
+            #[facet(bitcode(float = auto))]
+            auto: f32,
+            #[facet(bitcode(float = shuffle))]
+            shuffle: Vec<f32>,
+            #[facet(bitcode(quantize(bits = 12, min = 0, max = 1)))]
+            quantized: Vec<f32>,
+        }
+
+        let arc = Arc::new(vec![1, 2, 3]);
+        let everything = || Everything {
+            id: 7,
+            flag: true,
+            c: 'é',
+            name: "name",
+            a: vec![1, 2, 3],
+            b: vec![4, 5, 6],
+            nested: vec![vec![], vec![1, 2], vec![3]],
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Polygon {
+                    points: vec![(0, 0), (1, -1)],
+                    name: b"triangle".to_vec(),
+                },
+                Shape::Circle(2.5),
+            ],
+            arcs: vec![arc.clone(), Arc::new(vec![4]), arc.clone()],
+            boxed: Box::new(1),
+            sparse: (0..10)
+                .map(|i| Sparse(if i == 3 { 9 } else { 0 }))
+                .collect(),
+            xor: vec![1.0, 1.0, 2.5, -7.0],
+            auto: 3.25,
+            shuffle: vec![0.5, 0.25],
+            quantized: vec![0.1, 0.2, 0.3],
+        };
+        fn check<'a, T: Facet<'a>>(t: &T, options: EncodeOptions) {
+            let len = serialize_with(t, options).len();
+            assert_eq!(encoded_size_with(t, options), len);
+            // The lower bound `serialize` reserves.
+            let codec = crate::reflect(T::SHAPE, options);
+            assert!(unsafe { encoded_size_with_codec(codec, t, false) } <= len);
+        }
+        for optimize in [Optimize::Speed, Optimize::Size] {
+            for align in [false, true] {
+                let options = EncodeOptions { optimize, align };
+                check(&everything(), options);
+                let many: Vec<_> = (0..3).map(|_| everything()).collect();
+                check(&many, options);
+            }
+        }
+        assert_eq!(encoded_size(&5u8), 1);
+        assert_eq!(encoded_size(&vec![5u32; 3]), 16);
+    }
+
     #[bench]
     fn bench_nested_slice_facet_bitcode(b: &mut Bencher) {
         let v = nested_slice();
@@ -1,18 +1,22 @@
 use crate::codec::DynamicCodec;
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
 use crate::decoder::{decode_one_or_many, try_decode_in_place, Decoder};
-use crate::encoder::{encode_one_or_many, try_encode_in_place, Encoder};
+use crate::encoder::{encode_one_or_many, try_encode_in_place, Encoder, Values};
 use crate::error::{err, error, Result};
+use crate::output::Output;
 use crate::primitive::PrimitiveCodec;
 use crate::raw_vec_fork::RawVecInner;
+use crate::scratch::Scratch;
 use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::marker::PhantomData;
 use core::mem::{ManuallyDrop, MaybeUninit};
+use facet_core::{DropInPlaceFn, PtrMut};
 
 type LengthInt = u32; // TODO usize or u64.
 
 /// Types that can be converted to &[T] and from Box<[T]> in O(1).
-trait BoxedSliceLike {
+pub trait BoxedSliceLike {
     /// Shouldn't implement drop.
     type ErasedOwned;
 
@@ -24,9 +28,45 @@ trait BoxedSliceLike {
 
     /// Safety: `erased` must be a valid boxed slice (with unknown type).
     unsafe fn from_erased_boxed_slice(erased: *mut [u8]) -> Self::ErasedOwned;
+
+    /// Whether the elements are the bytes of a `str`.
+    const UTF8: bool = false;
+
+    /// Whether the elements are borrowed from the input (with [`Decoder::decode_borrowed`]) instead
+    /// of decoded.
+    const BORROWS_INPUT: bool = false;
+
+    /// Whether values own their elements, which decoding into them must drop or reuse.
+    const OWNED: bool = true;
+
+    /// Takes the allocation of an owned value, returning its elements and its capacity. Only
+    /// called if [`Self::OWNED`].
+    /// Safety: `erased` must be valid to read one instance of [`Self::ErasedOwned`].
+    unsafe fn into_raw_parts(_erased: *mut Self::ErasedOwned) -> (*mut [u8], usize) {
+        unreachable!();
+    }
+
+    /// Reuses an allocation from [`Self::into_raw_parts`] for `erased.len()` elements, which
+    /// mustn't exceed `capacity`. Returns `None` if it can't be reused. Only called if
+    /// [`Self::OWNED`].
+    /// Safety: `erased` must be allocated like [`Self::allocate`] with `capacity` elements.
+    unsafe fn from_raw_parts(_erased: *mut [u8], _capacity: usize) -> Option<Self::ErasedOwned> {
+        unreachable!();
+    }
+
+    /// Returns an error if [`Self::allocate`] would panic. Called before validating.
+    fn check_allocate() -> Result<()> {
+        Ok(())
+    }
+
+    /// Allocates a slice for [`Self::from_erased_boxed_slice`].
+    fn allocate(length: usize, element_layout: Layout) -> *mut [u8] {
+        allocate_erased_box(length, element_layout)
+    }
 }
 
 /// Indicates that the BoxedSliceCodec is for Box<[T]>.
+#[allow(dead_code)] // TODO Facet isn't implemented on Box<[T]> yet.
 pub struct BoxedSliceMarker;
 impl BoxedSliceLike for BoxedSliceMarker {
     type ErasedOwned = *mut [u8];
@@ -46,6 +86,16 @@ impl BoxedSliceLike for BoxedSliceMarker {
     unsafe fn from_erased_boxed_slice(erased: *mut [u8]) -> Self::ErasedOwned {
         erased
     }
+
+    unsafe fn into_raw_parts(erased: *mut Self::ErasedOwned) -> (*mut [u8], usize) {
+        let erased = unsafe { *erased };
+        (erased, erased.len())
+    }
+
+    unsafe fn from_raw_parts(erased: *mut [u8], capacity: usize) -> Option<Self::ErasedOwned> {
+        // A Box<[T]> has no spare capacity.
+        (erased.len() == capacity).then_some(erased)
+    }
 }
 
 /// Indicates that the BoxedSliceCodec is for Vec<T>.
@@ -78,87 +128,297 @@ impl BoxedSliceLike for VecMarker {
             erased.len(),
         ))
     }
+
+    unsafe fn into_raw_parts(erased: *mut Self::ErasedOwned) -> (*mut [u8], usize) {
+        // Safety: Caller guarentees that `erased` is valid to read.
+        let vec = unsafe { &mut *erased };
+        let slice: *mut [MaybeUninit<u8>] = vec.as_mut_slice();
+        (slice as *mut [u8], vec.capacity())
+    }
+
+    unsafe fn from_raw_parts(erased: *mut [u8], capacity: usize) -> Option<Self::ErasedOwned> {
+        Some(ManuallyDrop::new(Vec::from_raw_parts(
+            erased as *mut u8 as *mut MaybeUninit<u8>,
+            erased.len(),
+            capacity,
+        )))
+    }
+}
+
+/// Indicates that the BoxedSliceCodec is for &[T], which is allocated from the
+/// [`Arena`](crate::Arena) of [`deserialize_in`](crate::deserialize_in).
+pub struct ArenaMarker;
+impl BoxedSliceLike for ArenaMarker {
+    type ErasedOwned = *const [u8];
+
+    const OWNED: bool = false;
+
+    #[inline(always)]
+    unsafe fn as_erased_slice(erased: *const Self::ErasedOwned) -> *const [u8] {
+        // Safety: Caller guarentees that `erased` is valid to read.
+        unsafe { *erased }
+    }
+
+    #[inline(always)]
+    unsafe fn as_erased_slice_mut(erased: *mut Self::ErasedOwned) -> *mut [u8] {
+        // Only called while decoding into a newly allocated slice.
+        unsafe { *erased }.cast_mut()
+    }
+
+    #[inline(always)]
+    unsafe fn from_erased_boxed_slice(erased: *mut [u8]) -> Self::ErasedOwned {
+        erased
+    }
+
+    fn check_allocate() -> Result<()> {
+        #[cfg(feature = "std")]
+        return crate::arena::check_current();
+        #[cfg(not(feature = "std"))]
+        err("cannot deserialize &[T] without std")
+    }
+
+    fn allocate(length: usize, element_layout: Layout) -> *mut [u8] {
+        #[cfg(feature = "std")]
+        {
+            let (layout, _) = element_layout.repeat(length).unwrap();
+            core::ptr::slice_from_raw_parts_mut(crate::arena::alloc_current(layout), length)
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = (length, element_layout);
+            unreachable!(); // check_allocate returns an error.
+        }
+    }
+}
+
+/// Indicates that the BoxedSliceCodec is for &[u8] (or &str if `STR`), or &[T] of an aligned
+/// primitive, which borrows its elements from the input.
+pub struct InputMarker<const STR: bool>;
+impl<const STR: bool> BoxedSliceLike for InputMarker<STR> {
+    type ErasedOwned = *const [u8];
+
+    const BORROWS_INPUT: bool = true;
+    const OWNED: bool = false;
+    const UTF8: bool = STR;
+
+    #[inline(always)]
+    unsafe fn as_erased_slice(erased: *const Self::ErasedOwned) -> *const [u8] {
+        // Safety: Caller guarentees that `erased` is valid to read.
+        unsafe { *erased }
+    }
+
+    #[inline(always)]
+    unsafe fn as_erased_slice_mut(erased: *mut Self::ErasedOwned) -> *mut [u8] {
+        // Only the length is read, the bytes are never written.
+        unsafe { *erased }.cast_mut()
+    }
+
+    #[inline(always)]
+    unsafe fn from_erased_boxed_slice(erased: *mut [u8]) -> Self::ErasedOwned {
+        erased
+    }
+
+    fn allocate(length: usize, _: Layout) -> *mut [u8] {
+        // Placeholder with the length, pointed at the input by `decode_elements`.
+        core::ptr::slice_from_raw_parts_mut(core::ptr::NonNull::dangling().as_ptr(), length)
+    }
 }
 
 pub struct BoxedSliceCodec<T> {
     lengths: PrimitiveCodec<LengthInt>,
     element_layout: Layout,
     elements: DynamicCodec,
+    /// Drops an element when decoding into slices which have more of them than decoded.
+    drop_in_place: Option<DropInPlaceFn>,
     _spooky: PhantomData<fn(T)>,
 }
 
 impl<T> BoxedSliceCodec<T> {
-    pub fn new(element_layout: Layout, elements: DynamicCodec) -> Self {
+    pub fn new(
+        element_layout: Layout,
+        elements: DynamicCodec,
+        drop_in_place: Option<DropInPlaceFn>,
+    ) -> Self {
         Self {
             lengths: Default::default(),
             element_layout,
             elements,
+            drop_in_place,
             _spooky: PhantomData,
         }
     }
 }
 
-impl<T: BoxedSliceLike> Encoder for BoxedSliceCodec<T> {
-    unsafe fn encode_one(&self, erased: *const u8, out: &mut Vec<u8>) {
-        let slice = T::as_erased_slice(erased as *const T::ErasedOwned);
-        let len = slice.len() as LengthInt;
-        self.lengths
-            .encode_one((&len) as *const LengthInt as *const u8, out);
-        encode_one_or_many(&*self.elements, slice, out);
-    }
-
-    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Vec<u8>) {
-        self.encode_many_strided(erased, core::mem::size_of::<T::ErasedOwned>(), out);
-    }
-
-    #[inline(never)]
-    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Vec<u8>) {
-        let erased = erased as *const [T::ErasedOwned];
-
+impl<T: BoxedSliceLike> BoxedSliceCodec<T> {
+    /// Safety: `erased` must be valid to read `erased.len()` [`T::ErasedOwned`] at `stride`.
+    unsafe fn slices(
+        erased: *const [T::ErasedOwned],
+        stride: usize,
+    ) -> impl Iterator<Item = *const [u8]> + Clone {
         let mut slices_ptr = erased as *const T::ErasedOwned;
-        let slices = (0..erased.len()).map(move |_| {
+        (0..erased.len()).map(move |_| {
             let p = slices_ptr;
             unsafe { slices_ptr = slices_ptr.byte_add(stride) };
             T::as_erased_slice(p)
-        });
+        })
+    }
 
-        let mut n_elements = 0;
+    /// Safety: `erased` must be valid to write `erased.len()` [`T::ErasedOwned`] at `stride`.
+    unsafe fn slices_mut(
+        erased: *mut [T::ErasedOwned],
+        stride: usize,
+    ) -> impl Iterator<Item = *mut T::ErasedOwned> + Clone {
+        let mut slices_ptr = erased as *mut T::ErasedOwned;
+        (0..erased.len()).map(move |_| {
+            let p = slices_ptr;
+            unsafe { slices_ptr = slices_ptr.byte_add(stride) };
+            p
+        })
+    }
+
+    unsafe fn encode_elements(
+        &self,
+        slices: impl Iterator<Item = *const [u8]> + Clone,
+        n_elements: usize,
+        out: &mut Output,
+    ) {
+        let element_size = self.element_layout.size();
+        #[cfg(feature = "std")]
+        if self.elements.in_place()
+            && n_elements * element_size >= crate::output::MIN_DIRECT_SIZE
+            && out.writes_directly()
+        {
+            for slice in slices {
+                let bytes =
+                    core::slice::from_raw_parts(slice as *const u8, slice.len() * element_size);
+                out.extend_from_slice(bytes);
+            }
+            return;
+        }
         try_encode_in_place(
-            &self.lengths,
-            Layout::for_value(&(0 as LengthInt)),
-            erased.len(),
-            &mut |mut dst| {
-                // Rust doesn't put this value in a register unless we help it like this.
-                let mut n_elements_inner = 0;
+            &*self.elements,
+            self.element_layout,
+            n_elements,
+            &mut |mut dst: *mut u8| {
                 for slice in slices.clone() {
-                    n_elements_inner += slice.len();
-                    core::ptr::write_unaligned(dst as *mut LengthInt, slice.len() as LengthInt);
-                    dst = dst.byte_add(core::mem::size_of::<LengthInt>());
+                    let slice_len_bytes = slice.len().unchecked_mul(element_size);
+                    core::ptr::copy_nonoverlapping(slice as *const u8, dst, slice_len_bytes);
+                    dst = dst.byte_add(slice_len_bytes);
                 }
-                n_elements = n_elements_inner;
             },
             out,
         );
+    }
 
-        try_encode_in_place(
+    unsafe fn encoded_elements_size(
+        &self,
+        slices: impl Iterator<Item = *const [u8]>,
+        out_len: usize,
+        exact: bool,
+    ) -> usize {
+        let element_size = self.element_layout.size();
+        let fixed_size = if self.elements.in_place() {
+            Some(element_size)
+        } else {
+            self.elements.infallible_size()
+        };
+        if let Some(size) = fixed_size {
+            let n_elements: usize = slices.map(|slice| slice.len()).sum();
+            return out_len + n_elements * size;
+        }
+        let runs: Vec<_> = slices
+            .map(|slice| (slice as *const u8, slice.len()))
+            .collect();
+        self.elements
+            .encoded_size(Values::new(&runs, element_size), out_len, exact)
+    }
+
+    /// Safety: `slices` must have been allocated with lengths that sum to `n_elements`.
+    unsafe fn decode_elements(
+        &self,
+        input: &mut &[u8],
+        slices: impl Iterator<Item = *mut T::ErasedOwned> + Clone,
+        n_elements: usize,
+    ) {
+        if T::BORROWS_INPUT {
+            let mut src = self.elements.decode_borrowed(input, n_elements);
+            for boxed_slice_like in slices {
+                let length = T::as_erased_slice_mut(boxed_slice_like).len();
+                let slice = core::ptr::slice_from_raw_parts_mut(src.cast_mut(), length);
+                *boxed_slice_like = T::from_erased_boxed_slice(slice);
+                src = src.add(length * self.element_layout.size());
+            }
+            return;
+        }
+        try_decode_in_place(
             &*self.elements,
             self.element_layout,
             n_elements,
-            &mut |mut dst: *mut u8| {
+            &mut |mut src| {
                 let element_size = self.element_layout.size();
-                for slice in slices.clone() {
+                for boxed_slice_like in slices.clone() {
+                    let slice = T::as_erased_slice_mut(boxed_slice_like);
                     let slice_len_bytes = slice.len().unchecked_mul(element_size);
-                    core::ptr::copy_nonoverlapping(slice as *const u8, dst, slice_len_bytes);
-                    dst = dst.byte_add(slice_len_bytes);
+                    core::ptr::copy_nonoverlapping(src, slice as *mut u8, slice_len_bytes);
+                    src = src.byte_add(slice_len_bytes);
                 }
             },
-            out,
+            input,
         );
     }
 }
 
-impl<T: BoxedSliceLike> Decoder for BoxedSliceCodec<T> {
-    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+/// Lets [`StructCodec`](crate::struct_::StructCodec) write one length column for sibling slices
+/// that have equal lengths.
+pub trait SliceCodec {
+    /// Safety: `erased` must be a valid slice-like.
+    unsafe fn len(&self, erased: *const u8) -> usize;
+
+    /// Like [`Encoder::encode_many_strided`] without the length column.
+    unsafe fn encode_elements_strided(&self, erased: *const [u8], stride: usize, out: &mut Output);
+
+    /// Like [`Encoder::encoded_size`] without the length column.
+    unsafe fn encoded_elements_size(&self, values: Values, out_len: usize, exact: bool) -> usize;
+
+    /// Validates the length column and returns the sum of the lengths.
+    fn validate_lengths(&self, input: &mut &[u8], length: usize) -> Result<usize>;
+
+    /// Validates the element column (after the length column).
+    fn validate_elements(&self, input: &mut &[u8], n_elements: usize) -> Result<()>;
+
+    /// Like [`Decoder::decode_many_strided_into`] with the lengths from `lengths(index)` instead of
+    /// a length column.
+    /// Safety: the lengths must sum to what was passed to [`Self::validate_elements`].
+    unsafe fn decode_elements_strided_into(
+        &self,
+        input: &mut &[u8],
+        erased: *mut [u8],
+        stride: usize,
+        initialized: usize,
+        lengths: &mut dyn FnMut(usize) -> usize,
+    );
+}
+
+impl<T: BoxedSliceLike> SliceCodec for BoxedSliceCodec<T> {
+    unsafe fn len(&self, erased: *const u8) -> usize {
+        T::as_erased_slice(erased as *const T::ErasedOwned).len()
+    }
+
+    unsafe fn encode_elements_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        let slices = Self::slices(erased as *const [T::ErasedOwned], stride);
+        let n_elements = slices.clone().map(|slice| slice.len()).sum();
+        self.encode_elements(slices, n_elements, out);
+    }
+
+    unsafe fn encoded_elements_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        let slices = values
+            .iter()
+            .map(|erased| T::as_erased_slice(erased as *const T::ErasedOwned));
+        BoxedSliceCodec::encoded_elements_size(self, slices, out_len, exact)
+    }
+
+    fn validate_lengths(&self, input: &mut &[u8], length: usize) -> Result<usize> {
+        T::check_allocate()?;
         let before_lengths_consumed = *input;
         self.lengths.validate(input, length)?;
         // Safety: we validated that input contained enough bytes before
@@ -175,10 +435,143 @@ impl<T: BoxedSliceLike> Decoder for BoxedSliceCodec<T> {
             // Safety: we checked that there are no more than u32::MAX u32s, and u32::MAX * u32::MAX < u64::MAX.
             sum = unsafe { sum.unchecked_add(length as u64) };
         }
-        let sum = sum.try_into().map_err(|_| error("length > usize::MAX"))?;
+        sum.try_into().map_err(|_| error("length > usize::MAX"))
+    }
 
-        self.elements.validate(input, sum)?;
-        Ok(())
+    fn validate_elements(&self, input: &mut &[u8], n_elements: usize) -> Result<()> {
+        self.elements.validate(input, n_elements)
+    }
+
+    unsafe fn decode_elements_strided_into(
+        &self,
+        input: &mut &[u8],
+        erased: *mut [u8],
+        stride: usize,
+        initialized: usize,
+        lengths: &mut dyn FnMut(usize) -> usize,
+    ) {
+        let slices = Self::slices_mut(erased as *mut [T::ErasedOwned], stride);
+        let initialized = if T::OWNED { initialized } else { 0 };
+        let n_elements: usize = (0..erased.len()).map(&mut *lengths).sum();
+        let size = self.element_layout.size();
+
+        // Old elements which are decoded into instead of dropped, moved out of their slices.
+        let pool_elements = self.drop_in_place.is_some() && self.elements.can_decode_into();
+        let pool = Scratch::new(
+            self.element_layout,
+            if pool_elements { n_elements } else { 0 },
+        );
+        let mut pooled = 0;
+        for (i, slice) in slices.clone().enumerate() {
+            let length = lengths(i);
+            let mut reused = None;
+            if i < initialized {
+                let (old, capacity) = T::into_raw_parts(slice);
+                for j in 0..old.len() {
+                    let element = (old as *mut u8).byte_add(j * size);
+                    if pool_elements && pooled < n_elements {
+                        let dst = pool.as_ptr().byte_add(pooled * size);
+                        core::ptr::copy_nonoverlapping(element, dst, size);
+                        pooled += 1;
+                    } else if let Some(drop_in_place) = self.drop_in_place {
+                        drop_in_place(PtrMut::new(element));
+                    }
+                }
+                if length <= capacity {
+                    let erased = core::ptr::slice_from_raw_parts_mut(old as *mut u8, length);
+                    reused = T::from_raw_parts(erased, capacity);
+                }
+                if reused.is_none() {
+                    free_erased_box(old as *mut u8, capacity, self.element_layout);
+                }
+            }
+            *slice = reused.unwrap_or_else(|| {
+                T::from_erased_boxed_slice(T::allocate(length, self.element_layout))
+            });
+        }
+        if pooled == 0 {
+            return self.decode_elements(input, slices, n_elements);
+        }
+
+        let pool_slice = core::ptr::slice_from_raw_parts_mut(pool.as_ptr(), n_elements);
+        self.elements
+            .decode_many_strided_into(input, pool_slice, size, pooled);
+        let mut src = pool.as_ptr();
+        for boxed_slice_like in slices {
+            let slice = T::as_erased_slice_mut(boxed_slice_like);
+            let slice_len_bytes = slice.len().unchecked_mul(size);
+            core::ptr::copy_nonoverlapping(src, slice as *mut u8, slice_len_bytes);
+            src = src.byte_add(slice_len_bytes);
+        }
+    }
+}
+
+impl<T: BoxedSliceLike> Encoder for BoxedSliceCodec<T> {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        let slice = T::as_erased_slice(erased as *const T::ErasedOwned);
+        let len = slice.len() as LengthInt;
+        self.lengths
+            .encode_one((&len) as *const LengthInt as *const u8, out);
+        encode_one_or_many(&*self.elements, slice, out);
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        self.encode_many_strided(erased, core::mem::size_of::<T::ErasedOwned>(), out);
+    }
+
+    #[inline(never)]
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        let erased = erased as *const [T::ErasedOwned];
+        let slices = Self::slices(erased, stride);
+
+        let mut n_elements = 0;
+        try_encode_in_place(
+            &self.lengths,
+            Layout::for_value(&(0 as LengthInt)),
+            erased.len(),
+            &mut |mut dst| {
+                // Rust doesn't put this value in a register unless we help it like this.
+                let mut n_elements_inner = 0;
+                for slice in slices.clone() {
+                    n_elements_inner += slice.len();
+                    core::ptr::write_unaligned(dst as *mut LengthInt, slice.len() as LengthInt);
+                    dst = dst.byte_add(core::mem::size_of::<LengthInt>());
+                }
+                n_elements = n_elements_inner;
+            },
+            out,
+        );
+
+        self.encode_elements(slices, n_elements, out);
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        let out_len = out_len + values.len() * core::mem::size_of::<LengthInt>();
+        SliceCodec::encoded_elements_size(self, values, out_len, exact)
+    }
+
+    fn as_slice_codec(&self) -> Option<&dyn SliceCodec> {
+        // Validating a str needs its length, not just the sum of lengths.
+        (!T::UTF8).then_some(self)
+    }
+}
+
+impl<T: BoxedSliceLike> Decoder for BoxedSliceCodec<T> {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        let before_lengths_consumed = *input;
+        let sum = self.validate_lengths(input, length)?;
+        if T::UTF8 {
+            // The elements of a str are always raw bytes.
+            let mut bytes = consume_byte_arrays(input, sum, 1)?;
+            // Safety: validated the lengths above.
+            for length in unsafe { self.lengths.iter(before_lengths_consumed, length) } {
+                let (s, remaining) = bytes.split_at(length as usize);
+                core::str::from_utf8(s).map_err(|_| error("invalid UTF-8"))?;
+                bytes = remaining;
+            }
+            return Ok(());
+        }
+        self.validate_elements(input, sum)
     }
 
     unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
@@ -186,7 +579,13 @@ impl<T: BoxedSliceLike> Decoder for BoxedSliceCodec<T> {
         self.lengths
             .decode_one(input, length.as_mut_ptr() as *mut u8);
         let length = length.assume_init() as usize;
-        let erased_box = allocate_erased_box(length, self.element_layout);
+        if T::BORROWS_INPUT {
+            let src = self.elements.decode_borrowed(input, length);
+            let slice = core::ptr::slice_from_raw_parts_mut(src.cast_mut(), length);
+            unsafe { *(erased as *mut T::ErasedOwned) = T::from_erased_boxed_slice(slice) };
+            return;
+        }
+        let erased_box = T::allocate(length, self.element_layout);
         unsafe { *(erased as *mut T::ErasedOwned) = T::from_erased_boxed_slice(erased_box) };
         decode_one_or_many(&*self.elements, input, erased_box);
     }
@@ -198,13 +597,7 @@ impl<T: BoxedSliceLike> Decoder for BoxedSliceCodec<T> {
     #[inline(never)]
     unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
         let erased = erased as *mut [T::ErasedOwned];
-
-        let mut slices_ptr = erased as *mut T::ErasedOwned;
-        let slices = (0..erased.len()).map(move |_| {
-            let p = slices_ptr;
-            unsafe { slices_ptr = slices_ptr.byte_add(stride) };
-            p
-        });
+        let slices = Self::slices_mut(erased, stride);
 
         let mut n_elements = 0;
         try_decode_in_place(
@@ -216,30 +609,45 @@ impl<T: BoxedSliceLike> Decoder for BoxedSliceCodec<T> {
                     let length = core::ptr::read_unaligned(src as *const LengthInt) as usize;
                     src = src.byte_add(core::mem::size_of::<LengthInt>());
                     n_elements += length;
-                    *slice = T::from_erased_boxed_slice(allocate_erased_box(
-                        length,
-                        self.element_layout,
-                    ));
+                    *slice = T::from_erased_boxed_slice(T::allocate(length, self.element_layout));
                 }
             },
             input,
         );
 
-        try_decode_in_place(
-            &*self.elements,
-            self.element_layout,
-            n_elements,
-            &mut |mut src| {
-                let element_size = self.element_layout.size();
-                for boxed_slice_like in slices.clone() {
-                    let slice = T::as_erased_slice_mut(boxed_slice_like);
-                    let slice_len_bytes = slice.len().unchecked_mul(element_size);
-                    core::ptr::copy_nonoverlapping(src, slice as *mut u8, slice_len_bytes);
-                    src = src.byte_add(slice_len_bytes);
-                }
-            },
-            input,
-        );
+        self.decode_elements(input, slices, n_elements);
+    }
+
+    fn can_decode_into(&self) -> bool {
+        true
+    }
+
+    unsafe fn decode_many_strided_into(
+        &self,
+        input: &mut &[u8],
+        erased: *mut [u8],
+        stride: usize,
+        initialized: usize,
+    ) {
+        if initialized == 0 || !T::OWNED {
+            return self.decode_many_strided(input, erased, stride);
+        }
+        let lengths = consume_byte_arrays_unchecked(input, erased.len(), size_of::<LengthInt>());
+        let lengths = lengths.as_ptr() as *const LengthInt;
+        self.decode_elements_strided_into(input, erased, stride, initialized, &mut |i| {
+            // TODO swap_bytes on big endian.
+            lengths.add(i).read_unaligned() as usize
+        });
+    }
+}
+
+/// Frees an allocation of `capacity` elements from [`allocate_erased_box`].
+/// Safety: the elements must have been dropped or moved.
+unsafe fn free_erased_box(ptr: *mut u8, capacity: usize, element_layout: Layout) {
+    let size = capacity * element_layout.size();
+    if size != 0 {
+        let layout = Layout::from_size_align_unchecked(size, element_layout.align());
+        alloc::alloc::dealloc(ptr, layout);
     }
 }
 
@@ -0,0 +1,234 @@
+use crate::bits::{BitReader, BitWriter};
+use crate::codec::DynamicCodec;
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
+use crate::decoder::Decoder;
+use crate::encoder::{Encoder, Values};
+use crate::error::{err, Result};
+use crate::output::Output;
+use crate::pointer::scope;
+use crate::scratch::Scratch;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use facet_core::{DefaultInPlaceFn, DropInPlaceFn, PartialEqFn, PtrConst, PtrMut, PtrUninit};
+
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// How [`SparseCodec`] compares values with the default. Values must only be equal to the default if
+/// they decode as it, which `PartialEq` of floats doesn't guarantee (`-0.0 == 0.0`).
+pub enum DefaultEq {
+    /// By their bytes, for primitives (including floats).
+    Bytes,
+    /// By `PartialEq`, for types without floats.
+    PartialEq(PartialEqFn),
+    /// By their encoding, for the rest.
+    Encoding,
+}
+
+/// Codec for a column that usually holds the default value `#[facet(bitcode(sparse))]`.
+///
+/// Writes a tag byte followed by either the dense column or a bitmap of non-default positions and
+/// a column of only the non-default values, depending on whether the bitmap is smaller than the
+/// encoded default values it elides.
+pub struct SparseCodec {
+    values: DynamicCodec,
+    layout: Layout,
+    default_in_place: DefaultInPlaceFn,
+    drop_in_place: Option<DropInPlaceFn>,
+    default_eq: DefaultEq,
+}
+
+impl SparseCodec {
+    pub fn new(
+        values: DynamicCodec,
+        layout: Layout,
+        default_in_place: DefaultInPlaceFn,
+        drop_in_place: Option<DropInPlaceFn>,
+        default_eq: DefaultEq,
+    ) -> Self {
+        Self {
+            values,
+            layout,
+            default_in_place,
+            drop_in_place,
+            default_eq,
+        }
+    }
+
+    /// Safety: `pointers` must be valid to read values.
+    unsafe fn encode_gathered(&self, pointers: &[*const u8], out: &mut Output) {
+        let size = self.layout.size();
+        let gathered = Scratch::new(self.layout, pointers.len());
+        for (i, &p) in pointers.iter().enumerate() {
+            core::ptr::copy_nonoverlapping(p, gathered.as_ptr().byte_add(i * size), size);
+        }
+        self.values.encode_many(
+            core::ptr::slice_from_raw_parts(gathered.as_ptr(), pointers.len()),
+            out,
+        );
+    }
+
+    /// Returns whether each value isn't the default value and the encoded size of the default.
+    unsafe fn non_default(&self, values: impl Iterator<Item = *const u8>) -> (Vec<bool>, usize) {
+        let size = self.layout.size();
+        let default = Scratch::new(self.layout, 1);
+        (self.default_in_place)(PtrUninit::new(default.as_ptr()));
+        // Encoded on their own, so shared pointers don't refer back to the column's.
+        let encode_one = |value, encoded: &mut Vec<u8>| {
+            scope(|| self.values.encode_one(value, &mut Output::new(encoded)));
+        };
+        let mut encoded_default = vec![];
+        encode_one(default.as_ptr() as *const u8, &mut encoded_default);
+
+        let non_default = match self.default_eq {
+            DefaultEq::Bytes => {
+                let default = core::slice::from_raw_parts(default.as_ptr(), size);
+                values
+                    .map(|value| core::slice::from_raw_parts(value, size) != default)
+                    .collect()
+            }
+            DefaultEq::PartialEq(partial_eq) => values
+                .map(|value| !partial_eq(PtrConst::new(value), PtrConst::new(default.as_ptr())))
+                .collect(),
+            DefaultEq::Encoding => {
+                let mut encoded = vec![];
+                values
+                    .map(|value| {
+                        encoded.clear();
+                        encode_one(value, &mut encoded);
+                        encoded != encoded_default
+                    })
+                    .collect()
+            }
+        };
+        if let Some(drop_in_place) = self.drop_in_place {
+            drop_in_place(PtrMut::new(default.as_ptr()));
+        }
+        (non_default, encoded_default.len())
+    }
+
+    /// Whether the bitmap is at least as large as the defaults it would elide.
+    fn dense(non_default: &[bool], default_size: usize) -> bool {
+        let defaults = non_default.iter().filter(|&&b| !b).count();
+        non_default.len().div_ceil(8) >= defaults * default_size
+    }
+}
+
+impl Encoder for SparseCodec {
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        self.encode_many(core::ptr::slice_from_raw_parts(erased, 1), out);
+    }
+
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        self.encode_many_strided(erased, self.layout.size(), out);
+    }
+
+    unsafe fn encode_many_strided(&self, erased: *const [u8], stride: usize, out: &mut Output) {
+        let src = erased as *const u8;
+        let n = erased.len();
+        let (non_default, default_size) =
+            self.non_default((0..n).map(|i| src.byte_add(i * stride)));
+        if Self::dense(&non_default, default_size) {
+            out.push(DENSE);
+            if stride == self.layout.size() {
+                self.values.encode_many(erased, out);
+            } else {
+                let pointers: Vec<_> = (0..n).map(|i| src.byte_add(i * stride)).collect();
+                self.encode_gathered(&pointers, out);
+            }
+            return;
+        }
+        out.push(SPARSE);
+        let mut writer = BitWriter::new(out);
+        for &b in &non_default {
+            writer.write(b as u64, 1);
+        }
+        writer.finish();
+        let pointers: Vec<_> = (0..n)
+            .filter(|&i| non_default[i])
+            .map(|i| src.byte_add(i * stride))
+            .collect();
+        self.encode_gathered(&pointers, out);
+    }
+
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        if !exact {
+            // A sparse column is at least its bitmap.
+            let dense = self.values.encoded_size(values, out_len + 1, false);
+            return dense.min(out_len + 1 + values.len().div_ceil(8));
+        }
+        let (non_default, default_size) = self.non_default(values.iter());
+        if Self::dense(&non_default, default_size) {
+            return self.values.encoded_size(values, out_len + 1, true);
+        }
+        let runs: Vec<_> = values
+            .iter()
+            .zip(&non_default)
+            .filter(|&(_, &b)| b)
+            .map(|(value, _)| (value, 1))
+            .collect();
+        let out_len = out_len + 1 + values.len().div_ceil(8);
+        self.values
+            .encoded_size(Values::new(&runs, self.layout.size()), out_len, true)
+    }
+}
+
+impl Decoder for SparseCodec {
+    fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
+        match consume_byte_arrays(input, 1, 1)?[0] {
+            DENSE => self.values.validate(input, length),
+            SPARSE => {
+                let mut reader = BitReader::new(consume_byte_arrays(input, length.div_ceil(8), 1)?);
+                // Safety: consumed enough bytes for `length` bits above.
+                let count = (0..length)
+                    .filter(|_| unsafe { reader.read_unchecked(1) } != 0)
+                    .count();
+                self.values.validate(input, count)
+            }
+            _ => err("invalid sparse tag"),
+        }
+    }
+
+    unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
+        self.decode_many(input, core::ptr::slice_from_raw_parts_mut(erased, 1));
+    }
+
+    unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
+        self.decode_many_strided(input, erased, self.layout.size());
+    }
+
+    unsafe fn decode_many_strided(&self, input: &mut &[u8], erased: *mut [u8], stride: usize) {
+        let n = erased.len();
+        let size = self.layout.size();
+        let dst = erased as *mut u8;
+        let tag = consume_byte_arrays_unchecked(input, 1, 1)[0];
+        if tag == DENSE && stride == size {
+            return self.values.decode_many(input, erased);
+        }
+
+        let non_default: Vec<bool> = if tag == DENSE {
+            vec![true; n]
+        } else {
+            let mut reader = BitReader::new(consume_byte_arrays_unchecked(input, n.div_ceil(8), 1));
+            (0..n).map(|_| reader.read_unchecked(1) != 0).collect()
+        };
+        let count = non_default.iter().filter(|&&b| b).count();
+        let decoded = Scratch::new(self.layout, count);
+        self.values.decode_many(
+            input,
+            core::ptr::slice_from_raw_parts_mut(decoded.as_ptr(), count),
+        );
+        // Scatter the decoded values, filling in defaults between them.
+        let mut src = decoded.as_ptr() as *const u8;
+        for (i, &b) in non_default.iter().enumerate() {
+            let dst = dst.byte_add(i * stride);
+            if b {
+                core::ptr::copy_nonoverlapping(src, dst, size);
+                src = src.byte_add(size);
+            } else {
+                (self.default_in_place)(PtrUninit::new(dst));
+            }
+        }
+    }
+}
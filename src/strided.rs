@@ -0,0 +1,463 @@
+//! Copies of values between a strided layout (a field of many structs) and a packed one (a
+//! column).
+//!
+//! Values of 1, 2, 4 and 8 bytes are copied a 16 byte vector at a time (SSE2 on x86_64 and NEON
+//! on aarch64, which are always available): gathers build a vector from strided loads and store
+//! it at once, scatters load a vector at once and store its lanes. On x86_64 4 byte values are
+//! gathered with AVX2's hardware gather if it's detected at runtime (under `std`, otherwise if
+//! it's enabled at compile time). Whatever's left is copied one value at a time.
+//!
+//! Measured on x86_64 (4096 values with a stride of 16, ns, best of 2 runs):
+//!
+//! | size | gather scalar | gather SSE2 | gather AVX2 | scatter scalar | scatter SSE2 |
+//! |------|---------------|-------------|-------------|----------------|--------------|
+//! | 1    | 1984          | 1825        |             | 2204           | 1849         |
+//! | 2    | 1880          | 2029        |             | 1850           | 2157         |
+//! | 4    | 1357          | 1533        | 1458        | 2252           | 2144         |
+//! | 8    | 1537          | 1476        | 1942        | 2279           | 2112         |
+//!
+//! Runs vary by ±10%, so only the 1 byte kernels and the 8 byte gather are clearly faster. AVX2's
+//! 64 bit gather is clearly slower than two SSE2 loads, so it isn't used.
+
+/// Copies `n` values of `size` bytes which are `stride` bytes apart in `src` to `dst`.
+#[inline(always)]
+pub unsafe fn gather(size: usize, src: *const u8, stride: usize, dst: *mut u8, n: usize) {
+    match size {
+        1 => gather_simd::<1>(src, stride, dst, n),
+        2 => gather_simd::<2>(src, stride, dst, n),
+        4 => gather_simd::<4>(src, stride, dst, n),
+        8 => gather_simd::<8>(src, stride, dst, n),
+        _ => {
+            for i in 0..n {
+                // TODO swap_bytes on big endian.
+                core::ptr::copy_nonoverlapping(src.byte_add(i * stride), dst.add(i * size), size);
+            }
+        }
+    }
+}
+
+/// Copies `n` consecutive values of `size` bytes from `src` to `dst` with `stride` bytes between
+/// them.
+#[inline(always)]
+pub unsafe fn scatter(size: usize, src: *const u8, dst: *mut u8, stride: usize, n: usize) {
+    match size {
+        1 => scatter_simd::<1>(src, dst, stride, n),
+        2 => scatter_simd::<2>(src, dst, stride, n),
+        4 => scatter_simd::<4>(src, dst, stride, n),
+        8 => scatter_simd::<8>(src, dst, stride, n),
+        _ => {
+            for i in 0..n {
+                // TODO swap_bytes on big endian.
+                core::ptr::copy_nonoverlapping(src.add(i * size), dst.byte_add(i * stride), size);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn gather_simd<const N: usize>(src: *const u8, stride: usize, dst: *mut u8, n: usize) {
+    let done = simd::gather::<N>(src, stride, dst, n);
+    gather_n::<N>(
+        src.byte_add(done * stride),
+        stride,
+        dst.add(done * N),
+        n - done,
+    );
+}
+
+#[inline(always)]
+unsafe fn scatter_simd<const N: usize>(src: *const u8, dst: *mut u8, stride: usize, n: usize) {
+    let done = simd::scatter::<N>(src, dst, stride, n);
+    scatter_n::<N>(
+        src.add(done * N),
+        dst.byte_add(done * stride),
+        stride,
+        n - done,
+    );
+}
+
+#[inline(always)]
+unsafe fn gather_n<const N: usize>(src: *const u8, stride: usize, dst: *mut u8, n: usize) {
+    for i in 0..n {
+        // TODO swap_bytes on big endian.
+        core::ptr::copy_nonoverlapping(src.byte_add(i * stride), dst.add(i * N), N);
+    }
+}
+
+#[inline(always)]
+unsafe fn scatter_n<const N: usize>(src: *const u8, dst: *mut u8, stride: usize, n: usize) {
+    for i in 0..n {
+        // TODO swap_bytes on big endian.
+        core::ptr::copy_nonoverlapping(src.add(i * N), dst.byte_add(i * stride), N);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+use neon as simd;
+#[cfg(target_arch = "x86_64")]
+use x86 as simd;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod simd {
+    pub unsafe fn gather<const N: usize>(_: *const u8, _: usize, _: *mut u8, _: usize) -> usize {
+        0
+    }
+
+    pub unsafe fn scatter<const N: usize>(_: *const u8, _: *mut u8, _: usize, _: usize) -> usize {
+        0
+    }
+}
+
+/// The kernels return how many values were copied, the rest are left to [`gather_n`] and
+/// [`scatter_n`].
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use core::arch::x86_64::*;
+
+    #[inline(always)]
+    pub unsafe fn gather<const N: usize>(
+        src: *const u8,
+        stride: usize,
+        dst: *mut u8,
+        n: usize,
+    ) -> usize {
+        match N {
+            1 => gather_1(src, stride, dst, n),
+            2 => gather_2(src, stride, dst, n),
+            4 if avx2(stride) => gather_4_avx2(src, stride, dst, n),
+            4 => gather_4(src, stride, dst, n),
+            8 => gather_8(src, stride, dst, n),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn scatter<const N: usize>(
+        src: *const u8,
+        dst: *mut u8,
+        stride: usize,
+        n: usize,
+    ) -> usize {
+        match N {
+            1 => scatter_1(src, dst, stride, n),
+            2 => scatter_2(src, dst, stride, n),
+            4 => scatter_4(src, dst, stride, n),
+            8 => scatter_8(src, dst, stride, n),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Whether the AVX2 gather can be used, which takes 32 bit offsets.
+    #[inline(always)]
+    fn avx2(stride: usize) -> bool {
+        #[cfg(feature = "std")]
+        let detected = std::is_x86_feature_detected!("avx2");
+        #[cfg(not(feature = "std"))]
+        let detected = cfg!(target_feature = "avx2");
+        detected && stride <= i32::MAX as usize / 8
+    }
+
+    /// SSE2 can only insert 16 bit lanes, so bytes are inserted in pairs.
+    #[inline(always)]
+    unsafe fn gather_1(src: *const u8, stride: usize, dst: *mut u8, n: usize) -> usize {
+        let chunks = n / 16;
+        for chunk in 0..chunks {
+            let src = src.byte_add(chunk * 16 * stride);
+            let pair = |i: usize| {
+                let lo = *src.byte_add(2 * i * stride) as i32;
+                let hi = *src.byte_add((2 * i + 1) * stride) as i32;
+                lo | (hi << 8)
+            };
+            let mut v = _mm_setzero_si128();
+            macro_rules! insert {
+                ($($lane:literal),+) => {$(
+                    v = _mm_insert_epi16::<$lane>(v, pair($lane));
+                )+};
+            }
+            insert!(0, 1, 2, 3, 4, 5, 6, 7);
+            _mm_storeu_si128(dst.add(chunk * 16) as *mut __m128i, v);
+        }
+        chunks * 16
+    }
+
+    #[inline(always)]
+    unsafe fn gather_2(src: *const u8, stride: usize, dst: *mut u8, n: usize) -> usize {
+        let chunks = n / 8;
+        for chunk in 0..chunks {
+            let src = src.byte_add(chunk * 8 * stride);
+            let load = |i: usize| (src.byte_add(i * stride) as *const u16).read_unaligned() as i32;
+            let mut v = _mm_setzero_si128();
+            macro_rules! insert {
+                ($($lane:literal),+) => {$(
+                    v = _mm_insert_epi16::<$lane>(v, load($lane));
+                )+};
+            }
+            insert!(0, 1, 2, 3, 4, 5, 6, 7);
+            _mm_storeu_si128(dst.add(chunk * 16) as *mut __m128i, v);
+        }
+        chunks * 8
+    }
+
+    #[inline(always)]
+    unsafe fn gather_4(src: *const u8, stride: usize, dst: *mut u8, n: usize) -> usize {
+        let chunks = n / 4;
+        for chunk in 0..chunks {
+            let src = src.byte_add(chunk * 4 * stride);
+            let load = |i: usize| {
+                _mm_cvtsi32_si128((src.byte_add(i * stride) as *const i32).read_unaligned())
+            };
+            let lo = _mm_unpacklo_epi32(load(0), load(1));
+            let hi = _mm_unpacklo_epi32(load(2), load(3));
+            let v = _mm_unpacklo_epi64(lo, hi);
+            _mm_storeu_si128(dst.add(chunk * 16) as *mut __m128i, v);
+        }
+        chunks * 4
+    }
+
+    #[inline(always)]
+    unsafe fn gather_8(src: *const u8, stride: usize, dst: *mut u8, n: usize) -> usize {
+        let chunks = n / 2;
+        for chunk in 0..chunks {
+            let src = src.byte_add(chunk * 2 * stride);
+            let lo = _mm_loadl_epi64(src as *const __m128i);
+            let hi = _mm_loadl_epi64(src.byte_add(stride) as *const __m128i);
+            let v = _mm_unpacklo_epi64(lo, hi);
+            _mm_storeu_si128(dst.add(chunk * 16) as *mut __m128i, v);
+        }
+        chunks * 2
+    }
+
+    /// Safety: AVX2 must be available and `8 * stride` must fit in an `i32`.
+    #[target_feature(enable = "avx2")]
+    unsafe fn gather_4_avx2(src: *const u8, stride: usize, dst: *mut u8, n: usize) -> usize {
+        let s = stride as i32;
+        let offsets = _mm256_setr_epi32(0, s, 2 * s, 3 * s, 4 * s, 5 * s, 6 * s, 7 * s);
+        let chunks = n / 8;
+        for chunk in 0..chunks {
+            let src = src.byte_add(chunk * 8 * stride) as *const i32;
+            let v = _mm256_i32gather_epi32::<1>(src, offsets);
+            _mm256_storeu_si256(dst.add(chunk * 32) as *mut __m256i, v);
+        }
+        chunks * 8
+    }
+
+    /// Extracting 16 bit lanes is slow, so the vector is moved to two 64 bit registers which are
+    /// shifted out a byte at a time.
+    #[inline(always)]
+    unsafe fn scatter_1(src: *const u8, dst: *mut u8, stride: usize, n: usize) -> usize {
+        let chunks = n / 16;
+        for chunk in 0..chunks {
+            let v = _mm_loadu_si128(src.add(chunk * 16) as *const __m128i);
+            let halves = [
+                _mm_cvtsi128_si64(v),
+                _mm_cvtsi128_si64(_mm_unpackhi_epi64(v, v)),
+            ];
+            let dst = dst.byte_add(chunk * 16 * stride);
+            for (h, half) in halves.into_iter().enumerate() {
+                for i in 0..8 {
+                    *dst.byte_add((h * 8 + i) * stride) = (half >> (i * 8)) as u8;
+                }
+            }
+        }
+        chunks * 16
+    }
+
+    #[inline(always)]
+    unsafe fn scatter_2(src: *const u8, dst: *mut u8, stride: usize, n: usize) -> usize {
+        let chunks = n / 8;
+        for chunk in 0..chunks {
+            let v = _mm_loadu_si128(src.add(chunk * 16) as *const __m128i);
+            let dst = dst.byte_add(chunk * 8 * stride);
+            macro_rules! extract {
+                ($($lane:literal),+) => {$(
+                    let value = _mm_extract_epi16::<$lane>(v) as u16;
+                    (dst.byte_add($lane * stride) as *mut u16).write_unaligned(value);
+                )+};
+            }
+            extract!(0, 1, 2, 3, 4, 5, 6, 7);
+        }
+        chunks * 8
+    }
+
+    #[inline(always)]
+    unsafe fn scatter_4(src: *const u8, dst: *mut u8, stride: usize, n: usize) -> usize {
+        let chunks = n / 4;
+        for chunk in 0..chunks {
+            let v = _mm_loadu_si128(src.add(chunk * 16) as *const __m128i);
+            let dst = dst.byte_add(chunk * 4 * stride);
+            let store = |i: usize, v: __m128i| {
+                (dst.byte_add(i * stride) as *mut i32).write_unaligned(_mm_cvtsi128_si32(v))
+            };
+            store(0, v);
+            store(1, _mm_srli_si128::<4>(v));
+            store(2, _mm_srli_si128::<8>(v));
+            store(3, _mm_srli_si128::<12>(v));
+        }
+        chunks * 4
+    }
+
+    #[inline(always)]
+    unsafe fn scatter_8(src: *const u8, dst: *mut u8, stride: usize, n: usize) -> usize {
+        let chunks = n / 2;
+        for chunk in 0..chunks {
+            let v = _mm_loadu_si128(src.add(chunk * 16) as *const __m128i);
+            let dst = dst.byte_add(chunk * 2 * stride);
+            _mm_storel_epi64(dst as *mut __m128i, v);
+            _mm_storel_epi64(
+                dst.byte_add(stride) as *mut __m128i,
+                _mm_unpackhi_epi64(v, v),
+            );
+        }
+        chunks * 2
+    }
+}
+
+/// The kernels return how many values were copied, the rest are left to [`gather_n`] and
+/// [`scatter_n`].
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use core::arch::aarch64::*;
+
+    #[inline(always)]
+    pub unsafe fn gather<const N: usize>(
+        src: *const u8,
+        stride: usize,
+        dst: *mut u8,
+        n: usize,
+    ) -> usize {
+        match N {
+            1 => gather_1(src, stride, dst, n),
+            2 => gather_2(src, stride, dst, n),
+            4 => gather_4(src, stride, dst, n),
+            8 => gather_8(src, stride, dst, n),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn scatter<const N: usize>(
+        src: *const u8,
+        dst: *mut u8,
+        stride: usize,
+        n: usize,
+    ) -> usize {
+        match N {
+            1 => scatter_1(src, dst, stride, n),
+            2 => scatter_2(src, dst, stride, n),
+            4 => scatter_4(src, dst, stride, n),
+            8 => scatter_8(src, dst, stride, n),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Defines a gather of lanes `$t` (loaded with `$load` into a vector made with `$dup`, and
+    /// stored with `$store`) and a scatter (loading with `$load_all` and storing lanes with
+    /// `$store_lane`).
+    macro_rules! kernels {
+        (
+            $gather:ident, $scatter:ident, $t:ty, $dup:ident, $load:ident, $store:ident,
+            $load_all:ident, $store_lane:ident, [$($lane:literal),+]
+        ) => {
+            #[inline(always)]
+            unsafe fn $gather(src: *const u8, stride: usize, dst: *mut u8, n: usize) -> usize {
+                const LANES: usize = 16 / size_of::<$t>();
+                let chunks = n / LANES;
+                for chunk in 0..chunks {
+                    let src = src.byte_add(chunk * LANES * stride);
+                    let mut v = $dup(0);
+                    $(v = $load::<$lane>(src.byte_add($lane * stride) as *const $t, v);)+
+                    $store(dst.add(chunk * 16) as *mut $t, v);
+                }
+                chunks * LANES
+            }
+
+            #[inline(always)]
+            unsafe fn $scatter(src: *const u8, dst: *mut u8, stride: usize, n: usize) -> usize {
+                const LANES: usize = 16 / size_of::<$t>();
+                let chunks = n / LANES;
+                for chunk in 0..chunks {
+                    let v = $load_all(src.add(chunk * 16) as *const $t);
+                    let dst = dst.byte_add(chunk * LANES * stride);
+                    $($store_lane::<$lane>(dst.byte_add($lane * stride) as *mut $t, v);)+
+                }
+                chunks * LANES
+            }
+        };
+    }
+    kernels!(
+        gather_1,
+        scatter_1,
+        u8,
+        vdupq_n_u8,
+        vld1q_lane_u8,
+        vst1q_u8,
+        vld1q_u8,
+        vst1q_lane_u8,
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+    );
+    kernels!(
+        gather_2,
+        scatter_2,
+        u16,
+        vdupq_n_u16,
+        vld1q_lane_u16,
+        vst1q_u16,
+        vld1q_u16,
+        vst1q_lane_u16,
+        [0, 1, 2, 3, 4, 5, 6, 7]
+    );
+    kernels!(
+        gather_4,
+        scatter_4,
+        u32,
+        vdupq_n_u32,
+        vld1q_lane_u32,
+        vst1q_u32,
+        vld1q_u32,
+        vst1q_lane_u32,
+        [0, 1, 2, 3]
+    );
+    kernels!(
+        gather_8,
+        scatter_8,
+        u64,
+        vdupq_n_u64,
+        vld1q_lane_u64,
+        vst1q_u64,
+        vld1q_u64,
+        vst1q_lane_u64,
+        [0, 1]
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_gather_scatter() {
+        for size in [1, 2, 3, 4, 8] {
+            for stride in [size, size + 1, 16, 33] {
+                for n in [0, 1, 15, 16, 17, 100] {
+                    let strided: Vec<u8> = (0..n * stride).map(|i| (i * 7 + 3) as u8).collect();
+                    let expected: Vec<u8> = (0..n)
+                        .flat_map(|i| &strided[i * stride..i * stride + size])
+                        .copied()
+                        .collect();
+                    let mut packed = vec![0u8; n * size];
+                    unsafe { gather(size, strided.as_ptr(), stride, packed.as_mut_ptr(), n) };
+                    assert_eq!(packed, expected, "gather {size} {stride} {n}");
+
+                    // Bytes between the values are left alone.
+                    let mut scattered = vec![0xFFu8; n * stride];
+                    unsafe { scatter(size, packed.as_ptr(), scattered.as_mut_ptr(), stride, n) };
+                    for (i, chunk) in scattered.chunks(stride).enumerate() {
+                        assert_eq!(chunk[..size], expected[i * size..(i + 1) * size]);
+                        assert!(chunk[size..].iter().all(|&b| b == 0xFF));
+                    }
+                }
+            }
+        }
+    }
+}
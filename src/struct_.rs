@@ -1,9 +1,25 @@
+use crate::bits::{BitReader, BitWriter};
 use crate::codec::DynamicCodec;
+use crate::consume::{consume_byte_arrays, consume_byte_arrays_unchecked};
 use crate::decoder::Decoder;
-use crate::encoder::Encoder;
+use crate::encoder::{Encoder, Values};
 use crate::error::Result;
+use crate::output::Output;
+use crate::plan::Plan;
+use crate::slice::SliceCodec;
+use crate::strided;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Runs of at least this many small copied fields are encoded in tiles.
+const MIN_TILED_FIELDS: usize = 8;
+/// Fields larger than this (on average) are already fast enough to copy one column at a time.
+const MAX_TILED_FIELD_SIZE: usize = 4;
+/// Inputs smaller than this stay in cache when encoded one field at a time.
+const MIN_TILED_SIZE: usize = 1 << 20;
+/// Size of a tile of elements (fits in L1).
+const TILE_SIZE: usize = 4096;
 
 pub struct StructField {
     codec: DynamicCodec,
@@ -19,11 +35,33 @@ impl StructField {
             size,
         }
     }
+
+    pub fn codec(&self) -> &DynamicCodec {
+        &self.codec
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 }
 
 pub struct StructCodec {
     fields: Vec<StructField>,
     size: usize,
+    /// Indices of 2 or more fields that are slices. The first one always writes its length
+    /// column, preceded by a bit per other slice which indicates that it has the same lengths and
+    /// doesn't write its own.
+    slice_fields: Vec<usize>,
+    /// Encodes, validates and decodes one struct (if `slice_fields` is empty).
+    plan: Plan,
+    /// Runs of fields which are many small copies. Encoding or decoding one field at a time
+    /// accesses every element once per field, so these are transposed a tile of elements at a
+    /// time instead.
+    tiled_fields: Vec<Range<usize>>,
+    /// Sum of the fields' [`Decoder::infallible_size`]s if they're all infallible.
+    infallible_size: Option<usize>,
+    /// Whether all fields [`Decoder::can_decode_into`].
+    can_decode_into: bool,
 }
 
 impl StructCodec {
@@ -50,30 +88,248 @@ impl StructCodec {
                 debug_assert_eq!(single.offset, 0);
                 fields.pop().unwrap().codec
             }
-            _ => Box::new(Self { fields, size }),
+            _ => {
+                let mut slice_fields: Vec<_> = (0..fields.len())
+                    .filter(|&i| fields[i].codec.as_slice_codec().is_some())
+                    .collect();
+                if slice_fields.len() < 2 {
+                    slice_fields.clear();
+                }
+                let plan = Plan::new(&fields);
+                let tiled_fields = Self::tiled_fields(&fields);
+                let infallible_size = fields.iter().map(|f| f.codec.infallible_size()).sum();
+                let can_decode_into = fields.iter().all(|f| f.codec.can_decode_into());
+                Box::new(Self {
+                    fields,
+                    size,
+                    slice_fields,
+                    plan,
+                    tiled_fields,
+                    infallible_size,
+                    can_decode_into,
+                })
+            }
+        }
+    }
+
+    /// Finds runs of fields which are encoded as a copy of their bytes and are on average at
+    /// most [`MAX_TILED_FIELD_SIZE`].
+    fn tiled_fields(fields: &[StructField]) -> Vec<Range<usize>> {
+        let mut runs = Vec::new();
+        let mut i = 0;
+        while i < fields.len() {
+            let start = i;
+            while i < fields.len() && fields[i].codec.in_place() {
+                i += 1;
+            }
+            let run = &fields[start..i];
+            let size: usize = run.iter().map(|f| f.size).sum();
+            if run.len() >= MIN_TILED_FIELDS && size <= run.len() * MAX_TILED_FIELD_SIZE {
+                runs.push(start..i);
+            }
+            i += 1;
+        }
+        runs
+    }
+
+    /// Whether [`Self::tiled_fields`] are transposed a tile at a time for `n` elements.
+    fn tiled(&self, n: usize) -> bool {
+        n * self.size >= MIN_TILED_SIZE
+    }
+
+    /// Returns the start and number of elements of each tile of `n` elements.
+    fn tiles(&self, n: usize) -> impl Iterator<Item = (usize, usize)> {
+        let tile_rows = (TILE_SIZE / self.size).max(1);
+        (0..n)
+            .step_by(tile_rows)
+            .map(move |start| (start, tile_rows.min(n - start)))
+    }
+
+    /// Encodes the columns of `fields` (which must be copies) a tile of elements at a time, so
+    /// each element is loaded into cache once instead of once per field. Has the same output as
+    /// encoding each field with [`Encoder::encode_many_strided`].
+    unsafe fn encode_tiled(&self, fields: &[StructField], erased: *const [u8], out: &mut Output) {
+        let n = erased.len();
+        let row_size: usize = fields.iter().map(|f| f.size).sum();
+        out.extend_with(n * row_size, |columns| {
+            for (start, rows) in self.tiles(n) {
+                let src = (erased as *const u8).byte_add(start * self.size);
+                let mut column = columns;
+                for field in fields {
+                    let src = src.byte_add(field.offset);
+                    let dst = column.byte_add(start * field.size);
+                    strided::gather(field.size, src, self.size, dst, rows);
+                    column = column.byte_add(n * field.size);
+                }
+            }
+        });
+    }
+
+    /// Decodes the columns written by [`Self::encode_tiled`] a tile of elements at a time, so each
+    /// element is stored to once instead of once per field.
+    unsafe fn decode_tiled(&self, fields: &[StructField], input: &mut &[u8], erased: *mut [u8]) {
+        let n = erased.len();
+        let row_size: usize = fields.iter().map(|f| f.size).sum();
+        let columns = consume_byte_arrays_unchecked(input, n, row_size).as_ptr();
+        for (start, rows) in self.tiles(n) {
+            let dst = (erased as *mut u8).byte_add(start * self.size);
+            let mut column = columns;
+            for field in fields {
+                let src = column.add(start * field.size);
+                strided::scatter(field.size, src, dst.byte_add(field.offset), self.size, rows);
+                column = column.add(n * field.size);
+            }
+        }
+    }
+
+    /// Safety: `i` must be in `self.slice_fields`.
+    unsafe fn slice_field(&self, i: usize) -> (&StructField, &dyn SliceCodec) {
+        let field = self.fields.get_unchecked(i);
+        (field, field.codec.as_slice_codec().unwrap_unchecked())
+    }
+
+    /// Returns whether each of `self.slice_fields` has the same lengths as the first one in all
+    /// of the structs at `elements`.
+    unsafe fn shared_lengths(
+        &self,
+        elements: impl Iterator<Item = *const u8> + Clone,
+    ) -> Vec<bool> {
+        let Some((&first, rest)) = self.slice_fields.split_first() else {
+            return Vec::new();
+        };
+        let (first, first_codec) = self.slice_field(first);
+        let shared = rest.iter().map(|&i| {
+            let (field, codec) = self.slice_field(i);
+            elements.clone().all(|e| {
+                codec.len(e.byte_add(field.offset)) == first_codec.len(e.byte_add(first.offset))
+            })
+        });
+        core::iter::once(false).chain(shared).collect()
+    }
+
+    /// Decodes structs of which the first `initialized` are decoded into (see
+    /// [`Decoder::decode_many_strided_into`]).
+    unsafe fn decode_fields(&self, input: &mut &[u8], erased: *mut [u8], initialized: usize) {
+        let mut shared_lengths = Vec::new();
+        let mut slice_fields = self.slice_fields.iter().enumerate().peekable();
+        let tile = self.tiled(erased.len());
+        let mut tiled_fields = self.tiled_fields.iter().filter(|_| tile).peekable();
+        let mut fields = self.fields.iter().enumerate();
+        while let Some((i, field)) = fields.next() {
+            if let Some(run) = tiled_fields.next_if(|run| run.start == i) {
+                // Copies have nothing to drop, so decoding into them is the same.
+                self.decode_tiled(&self.fields[run.clone()], input, erased);
+                fields.nth(run.len() - 2);
+                continue;
+            }
+            let field_erased = erased.byte_add(field.offset);
+            if let Some((k, _)) = slice_fields.next_if(|(_, &j)| j == i) {
+                if k == 0 {
+                    shared_lengths = self.read_shared_lengths(input).unwrap_unchecked();
+                } else if shared_lengths[k] {
+                    // The first slice field was already decoded, so its lengths can be reused.
+                    let (first, first_codec) = self.slice_field(self.slice_fields[0]);
+                    let first_erased = (erased as *const u8).byte_add(first.offset);
+                    let (_, codec) = self.slice_field(i);
+                    codec.decode_elements_strided_into(
+                        input,
+                        field_erased,
+                        self.size,
+                        initialized,
+                        &mut |j| first_codec.len(first_erased.byte_add(j * self.size)),
+                    );
+                    continue;
+                }
+            }
+            field
+                .codec
+                .decode_many_strided_into(input, field_erased, self.size, initialized);
         }
     }
+
+    fn read_shared_lengths(&self, input: &mut &[u8]) -> Result<Vec<bool>> {
+        let n = self.slice_fields.len() - 1;
+        let mut reader = BitReader::new(consume_byte_arrays(input, n.div_ceil(8), 1)?);
+        // Safety: consumed enough bytes for `n` bits above.
+        let shared = (0..n).map(|_| unsafe { reader.read_unchecked(1) } != 0);
+        Ok(core::iter::once(false).chain(shared).collect())
+    }
 }
 
 impl Encoder for StructCodec {
-    unsafe fn encode_one(&self, erased: *const u8, out: &mut Vec<u8>) {
-        for field in &self.fields {
-            let erased = erased.byte_add(field.offset);
-            field.codec.encode_one(erased, out);
+    unsafe fn encode_one(&self, erased: *const u8, out: &mut Output) {
+        if !self.slice_fields.is_empty() {
+            return self.encode_many(core::ptr::slice_from_raw_parts(erased, 1), out);
         }
+        self.plan.encode_one(&self.fields, erased, out);
     }
 
-    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Vec<u8>) {
-        for field in &self.fields {
+    unsafe fn encode_many(&self, erased: *const [u8], out: &mut Output) {
+        let elements = (0..erased.len()).map(|i| (erased as *const u8).byte_add(i * self.size));
+        let shared_lengths = self.shared_lengths(elements);
+        let mut slice_fields = self
+            .slice_fields
+            .iter()
+            .zip(shared_lengths.iter())
+            .peekable();
+        let tile = self.tiled(erased.len());
+        let mut tiled_fields = self.tiled_fields.iter().filter(|_| tile).peekable();
+        let mut fields = self.fields.iter().enumerate();
+        while let Some((i, field)) = fields.next() {
+            if let Some(run) = tiled_fields.next_if(|run| run.start == i) {
+                self.encode_tiled(&self.fields[run.clone()], erased, out);
+                fields.nth(run.len() - 2);
+                continue;
+            }
             let erased = erased.byte_add(field.offset);
+            if let Some((_, &shared)) = slice_fields.next_if(|(&j, _)| j == i) {
+                if i == self.slice_fields[0] {
+                    let mut writer = BitWriter::new(out);
+                    for &shared in &shared_lengths[1..] {
+                        writer.write(shared as u64, 1);
+                    }
+                    writer.finish();
+                } else if shared {
+                    let (_, codec) = self.slice_field(i);
+                    codec.encode_elements_strided(erased, self.size, out);
+                    continue;
+                }
+            }
             field.codec.encode_many_strided(erased, self.size, out);
         }
     }
 
-    unsafe fn encode_many_strided(&self, _: *const [u8], _: usize, _: &mut Vec<u8>) {
+    unsafe fn encode_many_strided(&self, _: *const [u8], _: usize, _: &mut Output) {
         unreachable!(); // Struct codecs are flattened.
     }
 
+    unsafe fn encoded_size(&self, values: Values, out_len: usize, exact: bool) -> usize {
+        if let Some(size) = self.infallible_size {
+            return out_len + values.len() * size;
+        }
+        let shared_lengths = self.shared_lengths(values.iter());
+        let mut slice_fields = self
+            .slice_fields
+            .iter()
+            .zip(shared_lengths.iter())
+            .peekable();
+        let mut out_len = out_len;
+        for (i, field) in self.fields.iter().enumerate() {
+            let values = values.field(field.offset);
+            if let Some((_, &shared)) = slice_fields.next_if(|(&j, _)| j == i) {
+                if i == self.slice_fields[0] {
+                    out_len += (shared_lengths.len() - 1).div_ceil(8);
+                } else if shared {
+                    let (_, codec) = self.slice_field(i);
+                    out_len = codec.encoded_elements_size(values, out_len, exact);
+                    continue;
+                }
+            }
+            out_len = field.codec.encoded_size(values, out_len, exact);
+        }
+        out_len
+    }
+
     fn as_struct_codec_mut(&mut self) -> Option<&mut StructCodec> {
         Some(self)
     }
@@ -81,27 +337,70 @@ impl Encoder for StructCodec {
 
 impl Decoder for StructCodec {
     fn validate(&self, input: &mut &[u8], length: usize) -> Result<()> {
-        for field in &self.fields {
+        if let Some(size) = self.infallible_size {
+            // Empty structs have nothing to check (and can't divide by their size).
+            if size != 0 {
+                consume_byte_arrays(input, length, size)?;
+            }
+            return Ok(());
+        }
+        if length == 1 && self.slice_fields.is_empty() {
+            return self.plan.validate_one(&self.fields, input);
+        }
+        let mut shared_lengths = Vec::new();
+        let mut n_elements = 0;
+        let mut slice_fields = self.slice_fields.iter().enumerate().peekable();
+        for (i, field) in self.fields.iter().enumerate() {
+            if let Some((k, _)) = slice_fields.next_if(|(_, &j)| j == i) {
+                // Safety: `i` is in `self.slice_fields`.
+                let (_, codec) = unsafe { self.slice_field(i) };
+                if k == 0 {
+                    shared_lengths = self.read_shared_lengths(input)?;
+                    n_elements = codec.validate_lengths(input, length)?;
+                    codec.validate_elements(input, n_elements)?;
+                    continue;
+                } else if shared_lengths[k] {
+                    codec.validate_elements(input, n_elements)?;
+                    continue;
+                }
+            }
             field.codec.validate(input, length)?;
         }
         Ok(())
     }
 
+    fn infallible_size(&self) -> Option<usize> {
+        self.infallible_size
+    }
+
     unsafe fn decode_one(&self, input: &mut &[u8], erased: *mut u8) {
-        for field in &self.fields {
-            let erased = erased.byte_add(field.offset);
-            field.codec.decode_one(input, erased);
+        if !self.slice_fields.is_empty() {
+            return self.decode_many(input, core::ptr::slice_from_raw_parts_mut(erased, 1));
         }
+        self.plan.decode_one(&self.fields, input, erased);
     }
 
     unsafe fn decode_many(&self, input: &mut &[u8], erased: *mut [u8]) {
-        for field in &self.fields {
-            let erased = erased.byte_add(field.offset);
-            field.codec.decode_many_strided(input, erased, self.size);
-        }
+        self.decode_fields(input, erased, 0);
     }
 
     unsafe fn decode_many_strided(&self, _: &mut &[u8], _: *mut [u8], _: usize) {
         unreachable!(); // Struct codecs are flattened.
     }
+
+    fn can_decode_into(&self) -> bool {
+        self.can_decode_into
+    }
+
+    unsafe fn decode_many_strided_into(
+        &self,
+        input: &mut &[u8],
+        erased: *mut [u8],
+        stride: usize,
+        initialized: usize,
+    ) {
+        // Only called on whole structs (e.g. elements of a Vec), since fields are flattened.
+        debug_assert_eq!(stride, self.size);
+        self.decode_fields(input, erased, initialized);
+    }
 }
@@ -0,0 +1,115 @@
+use crate::codec::Codec;
+use crate::error::Result;
+use crate::options::EncodeOptions;
+use crate::output::Output;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use facet_core::Facet;
+
+/// The codec of a `T:` [`Facet`], resolved once so that encoding and decoding skip the codec
+/// cache. Has the same output as [`serialize_with`](crate::serialize_with) and
+/// [`deserialize_with`](crate::deserialize_with).
+///
+/// Without the `std` feature there's no cache, so [`TypedCodec::with_options`] constructs and leaks
+/// a new codec each time.
+pub struct TypedCodec<T: ?Sized> {
+    codec: &'static dyn Codec,
+    _marker: PhantomData<fn(&T) -> T>,
+}
+
+impl<T: ?Sized> Clone for TypedCodec<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for TypedCodec<T> {}
+
+impl<'facet, T: Facet<'facet> + ?Sized> TypedCodec<T> {
+    /// Resolves the codec of `T` with the default [`EncodeOptions`].
+    pub fn new() -> Self {
+        Self::with_options(EncodeOptions::default())
+    }
+
+    /// Resolves the codec of `T` with `options`.
+    pub fn with_options(options: EncodeOptions) -> Self {
+        #[cfg(feature = "std")]
+        let codec = crate::reflect(T::SHAPE, options);
+        #[cfg(not(feature = "std"))]
+        let codec = alloc::boxed::Box::leak(crate::reflect(T::SHAPE, options));
+        Self {
+            codec,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Like [`serialize`](crate::serialize).
+    pub fn encode(&self, t: &T) -> Vec<u8> {
+        // Safety: reflected from `T`.
+        unsafe { crate::serialize::serialize_with_codec(self.codec, t) }
+    }
+
+    /// Like [`serialize_into`](crate::serialize_into).
+    pub fn encode_into(&self, t: &T, out: &mut Vec<u8>) {
+        unsafe { (self.codec).encode_one(t as *const T as *const u8, &mut Output::new(out)) };
+    }
+
+    /// Like [`encoded_size`](crate::encoded_size).
+    pub fn encoded_size(&self, t: &T) -> usize {
+        // Safety: reflected from `T`.
+        unsafe { crate::serialize::encoded_size_with_codec(self.codec, t, true) }
+    }
+}
+
+impl<'facet, T: Facet<'facet>> TypedCodec<T> {
+    /// Like [`deserialize`](crate::deserialize).
+    pub fn decode(&self, bytes: &'facet [u8]) -> Result<T> {
+        // Safety: reflected from `T`.
+        unsafe { crate::deserialize::deserialize_with_codec(self.codec, bytes) }
+    }
+
+    /// Like [`deserialize_into`](crate::deserialize_into).
+    pub fn decode_into(&self, bytes: &'facet [u8], t: &mut T) -> Result<()> {
+        // Safety: reflected from `T`.
+        unsafe { crate::deserialize::deserialize_into_with_codec(self.codec, bytes, t) }
+    }
+}
+
+impl<'facet, T: Facet<'facet> + ?Sized> Default for TypedCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benches::Vertex;
+    use alloc::vec;
+
+    #[test]
+    fn test_typed_codec() {
+        fn assert_copy_send_sync<T: Copy + Send + Sync>(_: T) {}
+        let codec = crate::TypedCodec::<Vec<Vertex>>::new();
+        assert_copy_send_sync(codec);
+
+        let mesh: Vec<_> = (0..10).map(Vertex::new).collect();
+        let bytes = codec.encode(&mesh);
+        assert_eq!(bytes, crate::serialize(&mesh));
+        let mut out = vec![];
+        codec.encode_into(&mesh, &mut out);
+        assert_eq!(out, bytes);
+        assert_eq!(codec.decode(&bytes).unwrap(), mesh);
+        assert!(codec.decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let options = EncodeOptions {
+            optimize: crate::Optimize::Size,
+            ..Default::default()
+        };
+        let codec = crate::TypedCodec::<Vec<u32>>::with_options(options);
+        let ints: Vec<u32> = (0..100).collect();
+        let bytes = codec.encode(&ints);
+        assert_eq!(bytes, crate::serialize_with(&ints, options));
+        assert_eq!(codec.decode(&bytes).unwrap(), ints);
+    }
+}